@@ -1,75 +1,152 @@
-use artcore::{ApiChannels, AppConfig, ImageChannel, TokenAddressReceiver};
+use artcore::{
+    render_apng, render_png, render_svg, AnimationOptions, AppConfig, GeneratorRegistry,
+    GeneratorVersion, Overlays, PaletteSet, PathStyle, RenderOptions, RenderRequest, RenderService,
+    DEFAULT_GENERATOR,
+};
 use axum::{
-    extract::{Path, State},
-    http::header,
-    response::{AppendHeaders, IntoResponse},
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
 use serde::Deserialize;
+use std::{path::PathBuf, sync::Arc};
+use tokio::sync::Semaphore;
 use tower_http::trace::TraceLayer;
 
+/// Animations are much more work than a single image, so unless asked otherwise
+/// they are small and short, and there is a limit on how big and long they can be.
+const DEFAULT_APNG_WIDTH: u32 = 500;
+const MAX_APNG_WIDTH: u32 = 1000;
+const DEFAULT_APNG_FRAMES: u32 = 60;
+const MAX_APNG_FRAMES: u32 = 120;
+
+/// How many animations we draw at once, the rest wait their turn.
+const MAX_CONCURRENT_APNGS: usize = 2;
+
+#[derive(Clone, Debug)]
+enum Renderer {
+    /// Render using the Bevy app. This needs a GPU, and a display if `WINDOW` is set.
+    Bevy(RenderService),
+    /// Render using the software rasterizer. This needs no window, GPU or display.
+    Cpu,
+}
+
 #[derive(Clone, Debug)]
 struct MyState {
-    token_address_sender: crossbeam_channel::Sender<String>,
-    img_data_receiver: crossbeam_channel::Receiver<Vec<u8>>,
+    renderer: Renderer,
+    /// If not set, each generator's art is rendered at the width it is published at.
+    width: Option<u32>,
+    generators: GeneratorRegistry,
+    palettes: Arc<PaletteSet>,
+    overlays: Arc<Overlays>,
+    path_style: PathStyle,
+    apng_permits: Arc<Semaphore>,
 }
 
 #[tokio::main]
 async fn main() {
-    let width: f32 = std::env::var("WIDTH")
-        .unwrap_or_else(|_| "2000.0".to_string())
-        .parse()
-        .expect("WIDTH must be a float");
+    let width: Option<f32> = std::env::var("WIDTH")
+        .ok()
+        .map(|width| width.parse().expect("WIDTH must be a float"));
 
     let port: u16 = std::env::var("PORT")
         .unwrap_or_else(|_| "3131".to_string())
         .parse()
         .expect("PORT must be a u16");
 
-    let (img_data_sender, img_data_receiver) = crossbeam_channel::bounded(1);
-    let (token_address_sender, token_address_receiver) = crossbeam_channel::bounded(1);
+    let renderer = std::env::var("RENDERER").unwrap_or_else(|_| "bevy".to_string());
 
-    let state = MyState {
-        token_address_sender,
-        img_data_receiver,
-    };
+    // Have the Bevy app draw in a window, which needs a display, rather than
+    // offscreen.
+    let window = std::env::var("WINDOW").is_ok_and(|window| window == "true");
+    if window && !cfg!(feature = "window") {
+        panic!("WINDOW needs the api built with the window feature");
+    }
 
-    // Run the API in the background.
-    tokio::spawn(async move {
-        let app = Router::new()
-            .route("/", get(|| async { "Hello!" }))
-            .route("/:network/media/:address", get(handler))
-            .layer(TraceLayer::new_for_http())
-            .with_state(state);
+    // A YAML file with the palette set to use, otherwise we use the default one.
+    let palettes_path = std::env::var("PALETTES").ok().map(PathBuf::from);
+    let palettes = PaletteSet::load_or_default(palettes_path.as_deref())
+        .unwrap_or_else(|err| panic!("{}", err));
+    let palettes = Arc::new(palettes);
 
-        let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
-            .await
-            .unwrap();
+    // A YAML file declaring the overlays to draw over the art, otherwise we use the
+    // ones at DEFAULT_OVERLAYS.
+    let overlays_path = std::env::var("OVERLAYS").ok().map(PathBuf::from);
+    let overlays =
+        Overlays::load_or_default(overlays_path.as_deref()).unwrap_or_else(|err| panic!("{}", err));
+    let overlays = Arc::new(overlays);
 
-        eprintln!("Running server on port {}", port);
+    // Draw the ridgelines as smooth curves simplified to within this many scene
+    // units, otherwise there is a vertex per height sample.
+    let smooth_paths: Option<f32> = std::env::var("SMOOTH_PATHS")
+        .ok()
+        .map(|tolerance| tolerance.parse().expect("SMOOTH_PATHS must be a float"));
+    let path_style = PathStyle::from_tolerance(smooth_paths);
 
-        axum::serve(listener, app).await.unwrap();
-    });
+    let renderer = match renderer.as_str() {
+        "cpu" => Renderer::Cpu,
+        "bevy" => {
+            let app_config = AppConfig {
+                width,
+                generator: DEFAULT_GENERATOR.to_string(),
+                initial_token_address: "0x5".to_string(),
+                generator_version: GeneratorVersion::V1,
+                palettes: palettes_path,
+                smooth_paths,
+                overlays: overlays_path,
+                paused: true,
+                offscreen: !window,
+            };
+            // The app runs on a thread of its own.
+            let service = RenderService::start(app_config).unwrap_or_else(|err| panic!("{}", err));
+            Renderer::Bevy(service)
+        },
+        other => panic!("RENDERER must be either bevy or cpu, got {}", other),
+    };
 
-    let app_config = AppConfig {
-        width,
-        initial_token_address: "0x5".to_string(),
-        paused: true,
+    let state = MyState {
+        renderer,
+        width: width.map(|width| width as u32),
+        generators: GeneratorRegistry::default(),
+        palettes,
+        overlays,
+        path_style,
+        apng_permits: Arc::new(Semaphore::new(MAX_CONCURRENT_APNGS)),
     };
 
-    let mut bevy_app = app_config.build_for_api(None, ApiChannels {
-        image_channel: ImageChannel {
-            sender: img_data_sender,
-            // receiver: img_data_receiver,
-        },
-        token_address_receiver: TokenAddressReceiver {
-            receiver: token_address_receiver,
-        },
-    });
+    serve(state, port).await;
+}
+
+async fn serve(state: MyState, port: u16) {
+    let app = Router::new()
+        .route("/", get(|| async { "Hello!" }))
+        .route("/:network/media/:address", get(handler))
+        .route("/:network/media/:version/:address", get(versioned_handler))
+        .route("/:network/metadata/:address", get(metadata_handler))
+        .route(
+            "/:network/metadata/:version/:address",
+            get(versioned_metadata_handler),
+        )
+        .route(
+            "/:network/:generator/media/:version/:address",
+            get(generator_handler),
+        )
+        .route(
+            "/:network/:generator/metadata/:version/:address",
+            get(generator_metadata_handler),
+        )
+        .layer(TraceLayer::new_for_http())
+        .with_state(state);
 
-    // Run the app. This blocks because we don't use return_from_run.
-    bevy_app.run();
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+        .await
+        .unwrap();
+
+    eprintln!("Running server on port {}", port);
+
+    axum::serve(listener, app).await.unwrap();
 }
 
 #[derive(Deserialize)]
@@ -79,27 +156,239 @@ struct PathParams {
     address: String,
 }
 
+#[derive(Deserialize)]
+struct VersionedPathParams {
+    #[allow(dead_code)]
+    network: String,
+    version: GeneratorVersion,
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct GeneratorPathParams {
+    #[allow(dead_code)]
+    network: String,
+    generator: String,
+    version: GeneratorVersion,
+    address: String,
+}
+
+/// How big and long an animation should be, for `.apng` requests.
+#[derive(Deserialize)]
+struct AnimationParams {
+    width: Option<u32>,
+    frames: Option<u32>,
+}
+
+/// The images for the 2024 summit were published at this path before there were
+/// multiple versions of the generation algorithm, so it is always v1.
 async fn handler(
     Path(params): Path<PathParams>,
+    Query(animation): Query<AnimationParams>,
     State(state): State<MyState>,
-) -> impl IntoResponse {
-    // TODO: Single sempahore. Maybe not necessary.
+) -> Response {
+    render(
+        state,
+        DEFAULT_GENERATOR,
+        &params.address,
+        GeneratorVersion::V1,
+        animation,
+    )
+    .await
+}
 
-    // Trim extension.
-    let token_address = params.address.trim_end_matches(".png");
+async fn versioned_handler(
+    Path(params): Path<VersionedPathParams>,
+    Query(animation): Query<AnimationParams>,
+    State(state): State<MyState>,
+) -> Response {
+    render(
+        state,
+        DEFAULT_GENERATOR,
+        &params.address,
+        params.version,
+        animation,
+    )
+    .await
+}
 
-    // Send the token address to the app.
-    state
-        .token_address_sender
-        .send(token_address.to_string())
-        .unwrap();
+async fn generator_handler(
+    Path(params): Path<GeneratorPathParams>,
+    Query(animation): Query<AnimationParams>,
+    State(state): State<MyState>,
+) -> Response {
+    render(
+        state,
+        &params.generator,
+        &params.address,
+        params.version,
+        animation,
+    )
+    .await
+}
 
-    // Pull the image data the app eventually writes.
-    let image = state.img_data_receiver.recv().unwrap();
+/// Like `handler`, this is always v1.
+async fn metadata_handler(
+    Path(params): Path<PathParams>,
+    State(state): State<MyState>,
+) -> Response {
+    metadata(
+        state,
+        DEFAULT_GENERATOR,
+        &params.address,
+        GeneratorVersion::V1,
+    )
+}
 
-    let headers = AppendHeaders([(header::CONTENT_TYPE, "image/png")]);
+async fn versioned_metadata_handler(
+    Path(params): Path<VersionedPathParams>,
+    State(state): State<MyState>,
+) -> Response {
+    metadata(state, DEFAULT_GENERATOR, &params.address, params.version)
+}
+
+async fn generator_metadata_handler(
+    Path(params): Path<GeneratorPathParams>,
+    State(state): State<MyState>,
+) -> Response {
+    metadata(state, &params.generator, &params.address, params.version)
+}
 
-    println!("Returning image via API");
+/// The token's attributes, in the standard NFT metadata shape. These come straight
+/// from the scene, so they don't need the renderer.
+fn metadata(state: MyState, generator: &str, address: &str, version: GeneratorVersion) -> Response {
+    let generator = match state.generators.find(generator) {
+        Ok(generator) => generator,
+        Err(err) => return (StatusCode::NOT_FOUND, err.to_string()).into_response(),
+    };
+    let token_address = address.trim_end_matches(".json");
+    Json(generator.attributes(token_address, version, &state.palettes)).into_response()
+}
+
+async fn render(
+    state: MyState,
+    generator: &str,
+    address: &str,
+    version: GeneratorVersion,
+    animation: AnimationParams,
+) -> Response {
+    let generator = match state.generators.find(generator) {
+        Ok(generator) => generator,
+        Err(err) => return (StatusCode::NOT_FOUND, err.to_string()).into_response(),
+    };
+    let width = state.width.unwrap_or_else(|| generator.default_width());
+    let unsupported = |format: &str| {
+        let message = format!("{} can't render {}", generator.name(), format);
+        (StatusCode::NOT_FOUND, message).into_response()
+    };
 
-    (headers, image)
+    // SVGs are built straight from the scene, they don't need the renderer.
+    if let Some(token_address) = address.strip_suffix(".svg") {
+        let Some(svg) = render_svg(
+            generator.as_ref(),
+            token_address,
+            version,
+            &state.palettes,
+            &state.overlays,
+            width,
+            state.path_style,
+        ) else {
+            return unsupported("svg");
+        };
+        let headers = AppendHeaders([(header::CONTENT_TYPE, "image/svg+xml")]);
+        return (headers, svg.into_bytes()).into_response();
+    }
+
+    // The animation is drawn with the software rasterizer whichever renderer we use,
+    // the Bevy app only captures single frames.
+    if let Some(token_address) = address.strip_suffix(".apng") {
+        let options = AnimationOptions {
+            width: animation.width.unwrap_or(DEFAULT_APNG_WIDTH),
+            frames: animation.frames.unwrap_or(DEFAULT_APNG_FRAMES),
+            ..Default::default()
+        };
+        if options.width == 0 || options.width > MAX_APNG_WIDTH {
+            let message = format!("width must be between 1 and {}", MAX_APNG_WIDTH);
+            return (StatusCode::BAD_REQUEST, message).into_response();
+        }
+        if options.frames == 0 || options.frames > MAX_APNG_FRAMES {
+            let message = format!("frames must be between 1 and {}", MAX_APNG_FRAMES);
+            return (StatusCode::BAD_REQUEST, message).into_response();
+        }
+        let _permit = state
+            .apng_permits
+            .acquire()
+            .await
+            .expect("The semaphore is never closed");
+        let palettes = state.palettes.clone();
+        let overlays = state.overlays.clone();
+        let token_address = token_address.to_string();
+        let generator = generator.clone();
+        let apng = tokio::task::spawn_blocking(move || {
+            render_apng(
+                generator.as_ref(),
+                &token_address,
+                version,
+                &palettes,
+                &overlays,
+                &options,
+            )
+        })
+        .await;
+        let apng = match apng {
+            Ok(Some(Ok(apng))) => apng,
+            Ok(None) => return unsupported("apng"),
+            Ok(Some(Err(err))) => {
+                eprintln!("Failed to encode the animation of {}: {}", address, err);
+                let message = format!("Failed to encode the animation: {}", err);
+                return (StatusCode::INTERNAL_SERVER_ERROR, message).into_response();
+            },
+            Err(err) => {
+                eprintln!("Failed to animate {}: {}", address, err);
+                let message = err.to_string();
+                return (StatusCode::INTERNAL_SERVER_ERROR, message).into_response();
+            },
+        };
+        let headers = AppendHeaders([(header::CONTENT_TYPE, "image/apng")]);
+        return (headers, apng).into_response();
+    }
+
+    // Trim extension.
+    let token_address = address.trim_end_matches(".png");
+
+    let image = match state.renderer {
+        Renderer::Bevy(service) => {
+            let request = RenderRequest {
+                generator: generator.name().to_string(),
+                token_address: token_address.to_string(),
+                generator_version: version,
+            };
+            service.render(request).await.map_err(|err| err.to_string())
+        },
+        Renderer::Cpu => {
+            let options = RenderOptions {
+                generator: generator.name().to_string(),
+                version,
+                width: Some(width),
+                palettes: state.palettes.clone(),
+                overlays: state.overlays.clone(),
+                path_style: state.path_style,
+            };
+            let token_address = token_address.to_string();
+            tokio::task::spawn_blocking(move || render_png(&token_address, &options))
+                .await
+                .map_err(|err| err.to_string())
+                .and_then(|image| image.map_err(|err| err.to_string()))
+        },
+    };
+    let image = match image {
+        Ok(image) => image,
+        Err(message) => {
+            eprintln!("Failed to render {}: {}", token_address, message);
+            return (StatusCode::INTERNAL_SERVER_ERROR, message).into_response();
+        },
+    };
+
+    let headers = AppendHeaders([(header::CONTENT_TYPE, "image/png")]);
+    (headers, image).into_response()
 }
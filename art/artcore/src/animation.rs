@@ -0,0 +1,170 @@
+//! Export the parallax scroll of the live view as an animated PNG (APNG). We use APNG
+//! rather than GIF because GIF is limited to 256 colors, which bands the fog and haze
+//! badly, and every current browser supports APNG.
+//!
+//! The frames are drawn with the software rasterizer. Each layer scrolls at the speed
+//! it does in the live view (times `AnimationOptions::speed`), so the parallax is the
+//! same, but the random walk of the heights never repeats. So to make the loop
+//! seamless each frame is a crossfade of the scene drawn at two scroll positions one
+//! loop apart. The crossfade shifts from the one further along to the other over the
+//! course of the loop, so the last frame runs straight into the first. We fade the
+//! drawn frames rather than the heights, as blending the heights would flatten the
+//! ridgelines in the middle of the loop.
+
+use super::{
+    get_rng, parallax_speed, raster::height_at, rasterize_scene, CloudLayer, LayerSpec, PathStyle,
+    SceneSpec, SCENE_SIZE,
+};
+use image::RgbaImage;
+
+#[derive(Clone, Debug)]
+pub struct AnimationOptions {
+    /// The width (and height) of each frame.
+    pub width: u32,
+
+    /// How many frames the loop has.
+    pub frames: u32,
+
+    /// Frames per second.
+    pub fps: u16,
+
+    /// How much faster than the live view everything scrolls. At the speed of the
+    /// live view the back layers barely move in a loop of a few seconds.
+    pub speed: f32,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            width: 2000,
+            frames: 90,
+            fps: 30,
+            speed: 4.0,
+        }
+    }
+}
+
+impl AnimationOptions {
+    /// How long the loop is, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 / self.fps as f32
+    }
+}
+
+/// Render the frames of the loop. Each frame is only drawn when the iterator gets to
+/// it, so a long loop at a large width never has to fit in memory all at once.
+pub fn animate_scene(
+    scene: SceneSpec,
+    options: &AnimationOptions,
+) -> impl Iterator<Item = RgbaImage> {
+    assert!(
+        options.frames > 0 && options.fps > 0,
+        "An animation needs at least one frame and a frame rate"
+    );
+
+    let options = options.clone();
+    let duration = options.duration();
+    let speed = move |z: f32| parallax_speed(z) * options.speed;
+
+    // Each layer needs heights for two loops worth of scrolling plus what is visible,
+    // so keep generating them where the scene left off, just like the live view.
+    let mut rng = get_rng(scene.seed);
+    rng.set_word_pos(scene.rng_word_pos);
+    let layers: Vec<LayerSpec> = scene
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut layer = layer.clone();
+            let needed =
+                (2.0 * speed(layer.z) * duration).ceil() as usize + SCENE_SIZE as usize + 3;
+            while layer.heights.len() < needed {
+                layer.heights.push(layer.generator.next(&mut rng).unwrap());
+            }
+            layer
+        })
+        .collect();
+
+    (0..options.frames).map(move |frame| {
+        let time = frame as f32 / options.fps as f32;
+        // How far we are through the loop, and so through the crossfade.
+        let blend = time / duration;
+
+        let mut ahead = scroll_scene(&scene, &layers, speed, duration + time, options.width);
+        let behind = scroll_scene(&scene, &layers, speed, time, options.width);
+        for (ahead, behind) in ahead.iter_mut().zip(behind.iter()) {
+            *ahead = (*ahead as f32 * (1.0 - blend) + *behind as f32 * blend).round() as u8;
+        }
+        ahead
+    })
+}
+
+/// Draw the scene at the given width as it is after scrolling for the given time,
+/// with the layers that have enough heights to get there.
+fn scroll_scene(
+    scene: &SceneSpec,
+    layers: &[LayerSpec],
+    speed: impl Fn(f32) -> f32,
+    time: f32,
+    width: u32,
+) -> RgbaImage {
+    let mut frame_scene = scene.clone();
+    frame_scene.layers = layers
+        .iter()
+        .map(|layer| scroll_layer(layer, speed(layer.z) * time))
+        .collect();
+    for clouds in &mut frame_scene.sky.cloud_layers {
+        drift_clouds(clouds, speed(clouds.z) * time);
+    }
+
+    // Each frame only has the visible part of the ridgelines, with the anchors of a
+    // smooth path in a different place every frame, so we draw the heights as they
+    // are.
+    rasterize_scene(&frame_scene, width, PathStyle::Polyline)
+}
+
+/// The visible part of the layer's ridgeline after it has scrolled the given
+/// distance.
+fn scroll_layer(layer: &LayerSpec, distance: f32) -> LayerSpec {
+    let heights = (0..=SCENE_SIZE as usize + 1)
+        .map(|i| height_at(&layer.heights, distance + i as f32))
+        .collect();
+
+    LayerSpec {
+        heights,
+        ..layer.clone()
+    }
+}
+
+/// Move the clouds left by the given distance, wrapping them around like the live
+/// view does.
+fn drift_clouds(layer: &mut CloudLayer, distance: f32) {
+    for cloud in &mut layer.clouds {
+        let half_width = cloud.half_width();
+        let wrap = SCENE_SIZE + 2.0 * half_width;
+        cloud.x = (cloud.x - distance + half_width).rem_euclid(wrap) - half_width;
+    }
+}
+
+/// Encode the frames as an APNG that loops forever. Each frame is written as soon as
+/// it is drawn, so only the compressed loop is kept. There must be as many frames, of
+/// the size, as the options say.
+pub fn encode_apng(
+    frames: impl IntoIterator<Item = RgbaImage>,
+    options: &AnimationOptions,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, options.width, options.width);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // 0 plays means loop forever.
+    encoder.set_animated(options.frames, 0)?;
+    encoder.set_frame_delay(1, options.fps)?;
+
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(frame.as_raw())?;
+    }
+    writer.finish()?;
+
+    Ok(data)
+}
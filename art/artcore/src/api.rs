@@ -1,35 +1,52 @@
-use super::{spawn_mountains, Mountain, Randomness};
-use bevy::{
-    ecs::system::RunSystemOnce, prelude::*, render::view::screenshot::ScreenshotManager,
-    window::PrimaryWindow,
+use super::{
+    capture::{send_image, DrawnScene, PendingCapture},
+    encode_apng, AnimationOptions, AppSeed, ArtGenerator, GeneratorVersion, Overlays, PaletteSet,
+    PathStyle, RenderError, SvgOptions,
 };
-use crossbeam_channel::{Receiver, Sender};
-use image::{ImageOutputFormat, RgbaImage};
-use once_cell::sync::Lazy;
-use std::io::Cursor;
+use bevy::{ecs::system::RunSystemOnce, prelude::*, sprite::Mesh2dHandle};
+use crossbeam_channel::Receiver;
+use std::sync::Arc;
+use tokio::sync::oneshot;
 
+#[derive(Clone)]
 pub struct ApiChannels {
-    pub image_channel: ImageChannel,
     pub token_address_receiver: TokenAddressReceiver,
 }
 
-#[derive(Debug, Resource)]
-pub struct ImageChannel {
-    // Sender so we can send the image data back to the caller.
-    pub sender: Sender<Vec<u8>>,
-}
-
-#[derive(Debug, Resource)]
+#[derive(Clone, Debug, Resource)]
 pub struct TokenAddressReceiver {
     // Receiver so we can modify the mountains.
-    pub receiver: Receiver<String>,
+    pub receiver: Receiver<RenderJob>,
 }
 
-// This despawns the shapes drawn too.
-fn despawn_mountains(mut commands: Commands, mountains: Query<Entity, With<Mountain>>) {
-    for mountain in mountains.iter() {
-        commands.entity(mountain).despawn_recursive();
-    }
+/// Where to send the image data, or why there isn't any, back to the caller. This is
+/// a tokio channel so the caller can await it.
+pub type ImageReply = oneshot::Sender<Result<Vec<u8>, RenderError>>;
+
+/// A request with its own reply, so each caller gets the image they asked for however
+/// many are waiting.
+#[derive(Debug)]
+pub struct RenderJob {
+    pub request: RenderRequest,
+    pub reply: ImageReply,
+}
+
+/// What the app needs to render the art for the API, see `SummitsPlugin::api`.
+#[derive(Clone)]
+pub struct ApiOptions {
+    pub channels: ApiChannels,
+
+    /// Drawn over the images we send back.
+    pub overlays: Arc<Overlays>,
+}
+
+/// What we want the app to render.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderRequest {
+    /// The name of the generator to draw it with.
+    pub generator: String,
+    pub token_address: String,
+    pub generator_version: GeneratorVersion,
 }
 
 fn despawn_camera(mut commands: Commands, cameras: Query<Entity, With<Camera2d>>) {
@@ -38,108 +55,99 @@ fn despawn_camera(mut commands: Commands, cameras: Query<Entity, With<Camera2d>>
     }
 }
 
-pub fn token_address_listener(channel: Res<TokenAddressReceiver>, mut commands: Commands) {
-    if let Ok(token_address) = channel.receiver.try_recv() {
-        eprintln!("New token address: {}", token_address);
-        commands.insert_resource(Randomness::from_token_address(&token_address));
-        commands.add(move |world: &mut World| {
-            world.run_system_once(despawn_mountains);
-            world.run_system_once(despawn_camera);
-            world.run_system_once(spawn_mountains);
-            world.run_system_once(capture_frame);
-        });
-    }
-}
-
-// Because we do this at the screenshot layer, it means the texture and logo are
-// only visible in screenshots. This is fine for now since we're not going to use
-// the site at the moment, just drop the tokens.
-
-const NFT_TEXTURE: &'static [u8] =
-    include_bytes!("../../assets/aptos-ecosummit-2024_nft_texture_v2.png");
-const NFT_LOCKUP: &'static [u8] =
-    include_bytes!("../../assets/aptos-ecosummit-2024_nft_lockup.png");
-
-static NFT_TEXTURE_RGBA8: Lazy<RgbaImage> = Lazy::new(|| {
-    image::load_from_memory(NFT_TEXTURE)
-        .expect("Failed to load NFT texture")
-        .to_rgba8()
-});
-
-static NFT_LOCKUP_RGBA8: Lazy<RgbaImage> = Lazy::new(|| {
-    image::load_from_memory(NFT_LOCKUP)
-        .expect("Failed to load NFT lockup")
-        .to_rgba8()
-});
-
-fn capture_frame(
-    channel: Res<ImageChannel>,
-    main_window: Query<Entity, With<PrimaryWindow>>,
-    mut screenshot_manager: ResMut<ScreenshotManager>,
+pub(crate) fn token_address_listener(
+    channel: Res<TokenAddressReceiver>,
+    app_seed: Res<AppSeed>,
+    pending: Option<Res<PendingCapture>>,
+    mut commands: Commands,
 ) {
-    let sender = channel.sender.clone();
-    if sender.is_full() {
-        // eprintln!("Sender is full, not capturing frame");
+    // One job at a time, the rest wait in the channel.
+    if pending.is_some() {
         return;
     }
-    screenshot_manager
-        .take_screenshot(main_window.single(), move |image| {
-            let image = image
-                .try_into_dynamic()
-                .expect("Failed to convert image to dynamic");
-
-            let mut image = image.to_rgba8();
-
-            // This requires that the output width be the same size as the overlays.
-            blend_images_multiply(&mut image, vec![&NFT_TEXTURE_RGBA8]);
-            blend_images_replace(&mut image, vec![&NFT_LOCKUP_RGBA8]);
-
-            let mut buffer = Cursor::new(Vec::new());
-            image
-                .write_to(&mut buffer, ImageOutputFormat::Png)
-                .expect("Failed to write image as png");
-            let png_data: Vec<u8> = buffer.into_inner();
-
-            let result = sender.send(png_data);
-            match result {
-                Ok(_) => eprintln!("Sent image data"),
-                Err(e) => eprintln!("Failed to send image data {}", e),
+    if let Ok(RenderJob { request, reply }) = channel.receiver.try_recv() {
+        if reply.is_closed() {
+            eprintln!(
+                "Skipping {}, nobody is waiting for it",
+                request.token_address
+            );
+            return;
+        }
+        eprintln!(
+            "New token address: {} ({} {})",
+            request.token_address, request.generator, request.generator_version
+        );
+        let generator = match app_seed.generators.find(&request.generator) {
+            Ok(generator) => generator,
+            Err(err) => {
+                eprintln!("{}", err);
+                send_image(reply, Err(RenderError::UnknownGenerator(err)));
+                return;
+            },
+        };
+        // We don't know which generator drew what is there now, so have all of them
+        // clean up after themselves.
+        let generators = app_seed.generators.clone();
+        let palettes = app_seed.palettes.clone();
+        commands.add(move |world: &mut World| {
+            for generator in generators.iter() {
+                generator.despawn(world);
             }
-        })
-        .unwrap();
-}
-
-fn blend_images_multiply(base_image: &mut RgbaImage, images: Vec<&RgbaImage>) {
-    for (x, y, pixel) in base_image.enumerate_pixels_mut() {
-        for image in &images {
-            let image_pixel = image.get_pixel(x, y);
-            // Multiply blend mode formula: (Base * Overlay) / 255
-            // Apply this formula to each channel (R, G, B)
-            for i in 0..3 {
-                let base_val = pixel.0[i] as u16;
-                let overlay_val = image_pixel.0[i] as u16;
-
-                // Perform the multiply blend operation
-                pixel.0[i] = ((base_val * overlay_val) / 255) as u8;
+            world.run_system_once(despawn_camera);
+            generator.spawn(
+                world,
+                &request.token_address,
+                request.generator_version,
+                &palettes,
+            );
+            // Everything drawn now was spawned for this request, so tag it with the
+            // request for the render world to check the frames against.
+            let meshes: Vec<Entity> = world
+                .query_filtered::<Entity, With<Mesh2dHandle>>()
+                .iter(world)
+                .collect();
+            for entity in meshes {
+                world.entity_mut(entity).insert(DrawnScene(request.clone()));
             }
-        }
+            world.insert_resource(PendingCapture::new(request, reply));
+        });
     }
 }
 
-// Replace the pixels with the overlay images based on transparency.
-fn blend_images_replace(base_image: &mut RgbaImage, images: Vec<&RgbaImage>) {
-    for (x, y, pixel) in base_image.enumerate_pixels_mut() {
-        for image in &images {
-            let image_pixel = image.get_pixel(x, y);
-            // Overlay the pixels based on the alpha of image_pixel.
-            for i in 0..3 {
-                let base_val = pixel.0[i] as u16;
-                let overlay_val = image_pixel.0[i] as u16;
-                let alpha = image_pixel.0[3] as u16;
+/// Render the parallax scroll of the art for the given token address as a looping
+/// APNG, with the overlays on every frame. Like `render_png` this needs no window.
+/// Each frame is encoded as soon as it is drawn. Returns `None` if the generator can't
+/// animate its art.
+pub fn render_apng(
+    generator: &dyn ArtGenerator,
+    token_address: &str,
+    version: GeneratorVersion,
+    palettes: &PaletteSet,
+    overlays: &Overlays,
+    options: &AnimationOptions,
+) -> Option<Result<Vec<u8>, png::EncodingError>> {
+    let frames = generator.animate(token_address, version, palettes, options)?;
+    let frames = frames.map(|mut frame| {
+        overlays.apply(&mut frame);
+        frame
+    });
+    Some(encode_apng(frames, options))
+}
 
-                // Perform the multiply blend operation
-                pixel.0[i] = ((base_val * (255 - alpha) + overlay_val * alpha) / 255) as u8;
-            }
-        }
-    }
+/// Export the art for the given token address as an SVG, with the overlays. Returns
+/// `None` if the generator can't export SVGs.
+pub fn render_svg(
+    generator: &dyn ArtGenerator,
+    token_address: &str,
+    version: GeneratorVersion,
+    palettes: &PaletteSet,
+    overlays: &Overlays,
+    width: u32,
+    path_style: PathStyle,
+) -> Option<String> {
+    generator.svg(token_address, version, palettes, &SvgOptions {
+        width,
+        overlays: overlays.svg_overlays(),
+        path_style,
+    })
 }
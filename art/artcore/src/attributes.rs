@@ -0,0 +1,122 @@
+//! The traits of a token, in the shape marketplaces expect for NFT metadata:
+//!
+//! {"attributes": [{"trait_type": "Sky", "value": "Peach"}, ...]}
+//!
+//! These are read off the scene spec, so they always match the art: they come from
+//! the same RNG draws that the renderers draw from. Get a token's attributes from
+//! the generator that draws it, with `ArtGenerator::attributes`.
+
+use super::{CelestialKind, GeneratorVersion, SceneColor, SceneSpec};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TokenAttributes {
+    pub attributes: Vec<Attribute>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Attribute {
+    pub trait_type: String,
+
+    pub value: AttributeValue,
+
+    /// Set to "number" for numeric traits, so marketplaces show them as such.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_type: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    String(String),
+    Number(u64),
+}
+
+impl Attribute {
+    fn string(trait_type: &str, value: impl Into<String>) -> Self {
+        Attribute {
+            trait_type: trait_type.to_string(),
+            value: AttributeValue::String(value.into()),
+            display_type: None,
+        }
+    }
+
+    fn number(trait_type: &str, value: usize) -> Self {
+        Attribute {
+            trait_type: trait_type.to_string(),
+            value: AttributeValue::Number(value as u64),
+            display_type: Some("number".to_string()),
+        }
+    }
+}
+
+impl TokenAttributes {
+    pub fn from_scene(scene: &SceneSpec) -> Self {
+        let mut attributes = vec![Attribute::string("Generator", scene.version.to_string())];
+
+        // Palettes name their colors, v1 predates them so we name its colors here.
+        let (sky, mountains) = match &scene.palette_info {
+            Some(info) => {
+                attributes.push(Attribute::string("Palette", info.palette.as_str()));
+                (
+                    info.sky.clone().unwrap_or_else(|| hex(scene.palette.sky)),
+                    info.mountain_base
+                        .clone()
+                        .unwrap_or_else(|| hex(scene.palette.mountain_base)),
+                )
+            },
+            None => (
+                v1_color_name(scene.palette.sky),
+                v1_color_name(scene.palette.mountain_base),
+            ),
+        };
+        attributes.push(Attribute::string("Sky", sky));
+        attributes.push(Attribute::string("Mountains", mountains));
+        attributes.push(Attribute::number("Layers", scene.layers.len()));
+
+        // There was nothing in the sky and no fog before v2, so rather than saying
+        // every v1 token has none of these we leave them out.
+        if scene.version != GeneratorVersion::V1 {
+            let celestial_body = match scene.sky.celestial_body.as_ref().map(|body| body.kind) {
+                Some(CelestialKind::Sun) => "Sun",
+                Some(CelestialKind::Moon) => "Moon",
+                None => "None",
+            };
+            attributes.push(Attribute::string("Celestial Body", celestial_body));
+            let stars = if scene.sky.star_field.is_some() {
+                "Yes"
+            } else {
+                "No"
+            };
+            attributes.push(Attribute::string("Stars", stars));
+            attributes.push(Attribute::number(
+                "Cloud Layers",
+                scene.sky.cloud_layers.len(),
+            ));
+            let haze_bands = scene.fog.as_ref().map_or(0, |fog| fog.haze_bands.len());
+            attributes.push(Attribute::number("Haze Bands", haze_bands));
+        }
+
+        TokenAttributes { attributes }
+    }
+}
+
+fn v1_color_name(color: SceneColor) -> String {
+    let name = match color.as_rgba_u8() {
+        [255, 202, 140, _] => "Peach",
+        [211, 255, 154, _] => "Lime",
+        [71, 224, 226, _] => "Turquoise",
+        [129, 128, 85, _] => "Olive",
+        [167, 154, 93, _] => "Ochre",
+        [191, 182, 129, _] => "Sand",
+        [230, 230, 230, _] => "Snow",
+        [132, 134, 135, _] => "Slate",
+        _ => return hex(color),
+    };
+    name.to_string()
+}
+
+fn hex(color: SceneColor) -> String {
+    let [r, g, b, _] = color.as_rgba_u8();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
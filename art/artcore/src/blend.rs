@@ -0,0 +1,314 @@
+//! The blend modes overlays can be drawn with. These follow the W3C Compositing and
+//! Blending spec (https://www.w3.org/TR/compositing-1/), which is also what Photoshop
+//! and browsers do, so the overlays from design look the same here as they do in
+//! their tools and in the SVG export.
+//!
+//! Blending decides the color where the overlay and the art overlap, then that is
+//! composited over the art with source-over, weighted by the overlay's alpha times
+//! its opacity. Colors are in [0, 1] and not gamma corrected, like in those tools.
+//!
+//! Normal and multiply over opaque art are the blends the 2024 overlays are drawn
+//! with, so those are done in the same truncating integer math as before blend modes
+//! existed, and the published images of existing tokens don't change. Rounding the
+//! float math instead would move channels by one.
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// Just the overlay.
+    #[default]
+    Normal,
+
+    /// The art times the overlay, which always darkens. This is how the texture is
+    /// drawn.
+    Multiply,
+
+    /// The inverse of multiplying the inverses, which always lightens.
+    Screen,
+
+    /// Multiply the darks and screen the lights of the art, so its contrast is kept.
+    Overlay,
+
+    /// A gentler overlay, where the overlay darkens or lightens the art depending on
+    /// whether it is darker or lighter than mid grey.
+    SoftLight,
+
+    /// Brighten the art to reflect the overlay.
+    ColorDodge,
+}
+
+impl BlendMode {
+    /// The name of the blend mode in CSS, for `mix-blend-mode`.
+    pub fn css_name(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Overlay => "overlay",
+            BlendMode::SoftLight => "soft-light",
+            BlendMode::ColorDodge => "color-dodge",
+        }
+    }
+
+    /// Blend a channel of the overlay (the source) with a channel of the art (the
+    /// backdrop), ignoring alpha. This is B(Cb, Cs) in the spec.
+    pub fn blend(self, backdrop: f32, source: f32) -> f32 {
+        let (cb, cs) = (backdrop, source);
+        match self {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => screen(cb, cs),
+            BlendMode::Overlay => hard_light(cs, cb),
+            BlendMode::SoftLight => {
+                if cs <= 0.5 {
+                    cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+                } else {
+                    let d = if cb <= 0.25 {
+                        ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                    } else {
+                        cb.sqrt()
+                    };
+                    cb + (2.0 * cs - 1.0) * (d - cb)
+                }
+            },
+            BlendMode::ColorDodge => {
+                if cb == 0.0 {
+                    0.0
+                } else if cs >= 1.0 {
+                    1.0
+                } else {
+                    (cb / (1.0 - cs)).min(1.0)
+                }
+            },
+        }
+    }
+}
+
+fn screen(cb: f32, cs: f32) -> f32 {
+    cb + cs - cb * cs
+}
+
+/// Overlay is hard light with the arguments swapped, so we only need the one.
+fn hard_light(cb: f32, cs: f32) -> f32 {
+    if cs <= 0.5 {
+        cb * 2.0 * cs
+    } else {
+        screen(cb, 2.0 * cs - 1.0)
+    }
+}
+
+/// A pixel of an overlay, converted ready to composite. Overlays are drawn over many
+/// images, so we convert their pixels once rather than every time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourcePixel {
+    /// The straight (not premultiplied) color, in [0, 1].
+    pub color: [f32; 3],
+
+    /// The alpha of the pixel times the opacity of the overlay.
+    pub alpha: f32,
+
+    /// The pixel as it was, with the opacity applied to its alpha, for the integer
+    /// math.
+    pub bytes: [u8; 4],
+}
+
+impl SourcePixel {
+    pub fn new(source: [u8; 4], opacity: f32) -> Self {
+        let alpha = channel(source[3]) * opacity;
+        Self {
+            color: [channel(source[0]), channel(source[1]), channel(source[2])],
+            alpha,
+            bytes: [source[0], source[1], source[2], to_u8(alpha)],
+        }
+    }
+}
+
+/// Blend the source pixel with the backdrop pixel and composite the result over the
+/// backdrop. Both are straight (not premultiplied) RGBA, and so is the result. The
+/// opacity scales the alpha of the source.
+pub fn composite_pixel(
+    backdrop: [u8; 4],
+    source: [u8; 4],
+    mode: BlendMode,
+    opacity: f32,
+) -> [u8; 4] {
+    composite_source(backdrop, SourcePixel::new(source, opacity), mode)
+}
+
+/// Like `composite_pixel`, for a source pixel that has already been converted.
+pub fn composite_source(backdrop: [u8; 4], source: SourcePixel, mode: BlendMode) -> [u8; 4] {
+    if backdrop[3] == u8::MAX {
+        if let Some(result) = composite_opaque(backdrop, source.bytes, mode) {
+            return result;
+        }
+    }
+
+    let alpha_b = channel(backdrop[3]);
+    let alpha_s = source.alpha;
+
+    // A transparent source leaves the backdrop as it is. This is just a shortcut,
+    // the math below comes out the same, but most of an overlay like the lockup is
+    // transparent. A transparent backdrop is the exception, that comes out as zeros.
+    if alpha_s == 0.0 && alpha_b != 0.0 {
+        return backdrop;
+    }
+
+    // Source-over, in premultiplied terms: co = cs + cb * (1 - αs).
+    let alpha_o = alpha_s + alpha_b * (1.0 - alpha_s);
+    if alpha_o == 0.0 {
+        return [0; 4];
+    }
+
+    let mut result = [0; 4];
+    for i in 0..3 {
+        let (cb, cs) = (channel(backdrop[i]), source.color[i]);
+        // Where the backdrop is transparent there is nothing to blend with, so the
+        // source shows as it is.
+        let blended = (1.0 - alpha_b) * cs + alpha_b * mode.blend(cb, cs);
+        let premultiplied = alpha_s * blended + alpha_b * cb * (1.0 - alpha_s);
+        result[i] = to_u8(premultiplied / alpha_o);
+    }
+    result[3] = to_u8(alpha_o);
+    result
+}
+
+/// Normal and multiply over an opaque backdrop, in integer math. This is exactly how
+/// the 2024 overlays were drawn, see the module docs. The backdrop stays opaque.
+fn composite_opaque(backdrop: [u8; 4], source: [u8; 4], mode: BlendMode) -> Option<[u8; 4]> {
+    let blend: fn(u16, u16) -> u16 = match mode {
+        BlendMode::Normal => |_, cs| cs,
+        BlendMode::Multiply => |cb, cs| cb * cs / 255,
+        _ => return None,
+    };
+    let alpha_s = source[3] as u16;
+    let mut result = backdrop;
+    for i in 0..3 {
+        let (cb, cs) = (backdrop[i] as u16, source[i] as u16);
+        result[i] = ((cb * (255 - alpha_s) + blend(cb, cs) * alpha_s) / 255) as u8;
+    }
+    Some(result)
+}
+
+fn channel(value: u8) -> f32 {
+    value as f32 / 255.0
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_blend(mode: BlendMode, backdrop: f32, source: f32, expected: f32) {
+        let actual = mode.blend(backdrop, source);
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{:?} of {} and {} is {}, expected {}",
+            mode,
+            backdrop,
+            source,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn normal() {
+        assert_blend(BlendMode::Normal, 0.2, 0.7, 0.7);
+    }
+
+    #[test]
+    fn multiply() {
+        assert_blend(BlendMode::Multiply, 0.5, 0.5, 0.25);
+        assert_blend(BlendMode::Multiply, 1.0, 0.3, 0.3);
+    }
+
+    #[test]
+    fn screen() {
+        assert_blend(BlendMode::Screen, 0.5, 0.5, 0.75);
+        assert_blend(BlendMode::Screen, 0.0, 0.3, 0.3);
+    }
+
+    #[test]
+    fn overlay() {
+        // Dark backdrops are multiplied by twice the source.
+        assert_blend(BlendMode::Overlay, 0.25, 0.8, 0.4);
+        // Light ones are screened with twice the backdrop less one.
+        assert_blend(BlendMode::Overlay, 0.75, 0.5, 0.75);
+    }
+
+    #[test]
+    fn soft_light() {
+        // A dark source darkens.
+        assert_blend(BlendMode::SoftLight, 0.5, 0.25, 0.375);
+        // A light one lightens, towards the square root of light backdrops...
+        assert_blend(BlendMode::SoftLight, 0.64, 1.0, 0.8);
+        // ...and a polynomial of dark ones.
+        assert_blend(BlendMode::SoftLight, 0.25, 0.75, 0.375);
+    }
+
+    #[test]
+    fn color_dodge() {
+        assert_blend(BlendMode::ColorDodge, 0.0, 0.9, 0.0);
+        assert_blend(BlendMode::ColorDodge, 0.2, 1.0, 1.0);
+        assert_blend(BlendMode::ColorDodge, 0.25, 0.5, 0.5);
+        assert_blend(BlendMode::ColorDodge, 0.75, 0.5, 1.0);
+    }
+
+    #[test]
+    fn opaque_backdrops_truncate_like_the_original_compositing() {
+        let multiplied = composite_pixel(
+            [200, 200, 200, 255],
+            [200, 0, 255, 255],
+            BlendMode::Multiply,
+            1.0,
+        );
+        assert_eq!(multiplied, [156, 0, 200, 255]);
+
+        let replaced = composite_pixel(
+            [200, 100, 0, 255],
+            [0, 100, 255, 128],
+            BlendMode::Normal,
+            1.0,
+        );
+        assert_eq!(replaced, [99, 100, 128, 255]);
+    }
+
+    #[test]
+    fn opacity_scales_the_source_alpha() {
+        let half = composite_pixel([0, 0, 0, 255], [255, 255, 255, 255], BlendMode::Normal, 0.5);
+        let half_alpha =
+            composite_pixel([0, 0, 0, 255], [255, 255, 255, 128], BlendMode::Normal, 1.0);
+        assert_eq!(half, half_alpha);
+    }
+
+    #[test]
+    fn transparent_backdrops_show_the_source() {
+        let result = composite_pixel(
+            [10, 20, 30, 0],
+            [200, 100, 50, 255],
+            BlendMode::Multiply,
+            1.0,
+        );
+        assert_eq!(result, [200, 100, 50, 255]);
+
+        // Half covered, the source is blended with half of the backdrop.
+        let result = composite_pixel([255, 255, 255, 128], [0, 0, 0, 255], BlendMode::Screen, 1.0);
+        assert_eq!(result, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn transparent_sources_leave_the_backdrop() {
+        for mode in [BlendMode::Screen, BlendMode::Overlay, BlendMode::SoftLight] {
+            let backdrop = [12, 34, 56, 200];
+            assert_eq!(
+                composite_pixel(backdrop, [255, 0, 0, 0], mode, 1.0),
+                backdrop
+            );
+        }
+    }
+}
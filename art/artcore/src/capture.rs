@@ -0,0 +1,393 @@
+//! Capturing the frames the API asks for. Spawning a scene doesn't mean the next
+//! frame shows it: its meshes, materials and pipelines can take a few frames to be
+//! ready, and until then frames are drawn with parts missing. So we capture frame
+//! after frame, and the render world checks whether each one drew all of the scene
+//! that was asked for. The first that did is the one we send back.
+//!
+//! In a window we capture frames with screenshots. Offscreen the render world copies
+//! them out of the `OffscreenTarget` itself.
+
+use super::{encode_png, ImageReply, OffscreenTarget, Overlays, RenderError, RenderRequest};
+use bevy::{
+    core_pipeline::core_2d::Transparent2d,
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_phase::RenderPhase,
+        render_resource::{
+            BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+            ImageDataLayout, MapMode, PipelineCache, TextureDimension,
+        },
+        renderer::{render_system, RenderDevice, RenderQueue},
+        texture::TextureFormatPixelInfo,
+        view::screenshot::ScreenshotManager,
+        Render, RenderApp, RenderSet,
+    },
+    sprite::{RenderMaterial2dInstances, RenderMaterials2d, RenderMesh2dInstances},
+    tasks::AsyncComputeTaskPool,
+    window::PrimaryWindow,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// How long we give a scene to be drawn before we give up on it. This is a time
+/// rather than a number of frames because the first scene waits for every pipeline
+/// to compile, which takes much longer than a few frames on a slow GPU.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub(crate) struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<DrawnScene>::default(),
+            ExtractResourcePlugin::<CurrentCapture>::default(),
+            ExtractResourcePlugin::<OffscreenTarget>::default(),
+        ))
+        .add_systems(Update, poll_capture);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        // Check before the frame is drawn, so whoever captures it knows whether the
+        // frame was complete. We can only copy the frame out once it's drawn.
+        render_app.add_systems(
+            Render,
+            (
+                check_frame.in_set(RenderSet::Render).before(render_system),
+                read_back_frame
+                    .in_set(RenderSet::Render)
+                    .after(render_system),
+            ),
+        );
+    }
+}
+
+/// The overlays drawn over the frames the app captures. Because we draw them at the
+/// screenshot layer they are only in the images we send back, not in the window.
+#[derive(Resource)]
+pub(crate) struct FrameOverlays(pub(crate) Arc<Overlays>);
+
+/// The request a mesh of the scene was spawned for.
+#[derive(Clone, Component, ExtractComponent)]
+pub(crate) struct DrawnScene(pub(crate) RenderRequest);
+
+/// A job whose scene has been spawned, waiting for a frame that shows it. The app
+/// doesn't take another job until this one is answered.
+#[derive(Resource)]
+pub(crate) struct PendingCapture {
+    pub(crate) request: RenderRequest,
+    pub(crate) reply: Option<ImageReply>,
+    pub(crate) started: Instant,
+    pub(crate) attempt: Option<Arc<CaptureAttempt>>,
+}
+
+impl PendingCapture {
+    pub(crate) fn new(request: RenderRequest, reply: ImageReply) -> Self {
+        Self {
+            request,
+            reply: Some(reply),
+            started: Instant::now(),
+            attempt: None,
+        }
+    }
+}
+
+/// The attempt the render world should check the next frame against.
+#[derive(Clone, Resource, ExtractResource)]
+struct CurrentCapture(Arc<CaptureAttempt>);
+
+/// A capture of one frame, shared by the main world, the render world and whoever
+/// captures the frame.
+pub(crate) struct CaptureAttempt {
+    request: RenderRequest,
+    overlays: Arc<Overlays>,
+    /// One of the `FRAME_` constants, set by the render world.
+    frame: AtomicU8,
+    /// Whether the render world has started copying the frame out, when drawing
+    /// offscreen. It sees the attempt every frame until the main world replaces it.
+    read_back: AtomicBool,
+    /// Set once the frame is captured.
+    outcome: Mutex<Option<CaptureOutcome>>,
+}
+
+const FRAME_UNCHECKED: u8 = 0;
+const FRAME_COMPLETE: u8 = 1;
+const FRAME_INCOMPLETE: u8 = 2;
+
+enum CaptureOutcome {
+    Captured(Result<Vec<u8>, RenderError>),
+    /// The frame was missing some of the scene, so try the next one.
+    Incomplete,
+}
+
+impl CaptureAttempt {
+    fn new(request: RenderRequest, overlays: Arc<Overlays>) -> Self {
+        Self {
+            request,
+            overlays,
+            frame: AtomicU8::new(FRAME_UNCHECKED),
+            read_back: AtomicBool::new(false),
+            outcome: Mutex::new(None),
+        }
+    }
+
+    /// Only the first frame after the attempt was made counts, that is the one that
+    /// is captured.
+    fn check(&self, complete: bool) {
+        let frame = if complete {
+            FRAME_COMPLETE
+        } else {
+            FRAME_INCOMPLETE
+        };
+        self.frame
+            .compare_exchange(FRAME_UNCHECKED, frame, Ordering::AcqRel, Ordering::Acquire)
+            .ok();
+    }
+
+    fn complete(&self) -> bool {
+        self.frame.load(Ordering::Acquire) == FRAME_COMPLETE
+    }
+
+    fn finish(&self, outcome: CaptureOutcome) {
+        *self.outcome.lock().unwrap() = Some(outcome);
+    }
+
+    /// Finish with the captured frame, if it was complete.
+    fn captured(&self, image: Image) {
+        if !self.complete() {
+            self.finish(CaptureOutcome::Incomplete);
+            return;
+        }
+        let png_data = match image.try_into_dynamic() {
+            Ok(image) => {
+                let mut image = image.to_rgba8();
+                self.overlays.apply(&mut image);
+                encode_png(&image).map_err(RenderError::Encode)
+            },
+            Err(err) => Err(RenderError::Capture(err.to_string())),
+        };
+        self.finish(CaptureOutcome::Captured(png_data));
+    }
+}
+
+/// Send back the image of the pending capture once we have it, or capture another
+/// frame if we don't yet.
+fn poll_capture(
+    mut commands: Commands,
+    pending: Option<ResMut<PendingCapture>>,
+    overlays: Res<FrameOverlays>,
+    offscreen: Option<Res<OffscreenTarget>>,
+    main_window: Query<Entity, With<PrimaryWindow>>,
+    mut screenshot_manager: ResMut<ScreenshotManager>,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+
+    // See how the last capture went, if it's done.
+    let outcome = pending
+        .attempt
+        .as_ref()
+        .and_then(|attempt| attempt.outcome.lock().unwrap().take());
+    if outcome.is_some() {
+        pending.attempt = None;
+    }
+    let result = match outcome {
+        Some(CaptureOutcome::Captured(result)) => Some(result),
+        _ if pending.started.elapsed() > CAPTURE_TIMEOUT => {
+            Some(Err(RenderError::Capture(format!(
+                "{} wasn't drawn within {} seconds",
+                pending.request.token_address,
+                CAPTURE_TIMEOUT.as_secs()
+            ))))
+        },
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Some(reply) = pending.reply.take() {
+            send_image(reply, result);
+        }
+        commands.remove_resource::<PendingCapture>();
+        return;
+    }
+
+    // Wait for the capture we already asked for.
+    if pending.attempt.is_some() {
+        return;
+    }
+
+    let attempt = Arc::new(CaptureAttempt::new(
+        pending.request.clone(),
+        overlays.0.clone(),
+    ));
+
+    // The render world reads the frame back without being asked.
+    if offscreen.is_some() {
+        commands.insert_resource(CurrentCapture(attempt.clone()));
+        pending.attempt = Some(attempt);
+        return;
+    }
+
+    let callback_attempt = attempt.clone();
+    let screenshot = screenshot_manager.take_screenshot(main_window.single(), move |image| {
+        callback_attempt.captured(image)
+    });
+    match screenshot {
+        Ok(()) => {
+            commands.insert_resource(CurrentCapture(attempt.clone()));
+            pending.attempt = Some(attempt);
+        },
+        // Something else is taking a screenshot, try again next frame.
+        Err(err) => eprintln!("Not capturing this frame: {}", err),
+    }
+}
+
+/// Check whether the frame being drawn has all of the scene the current attempt is
+/// for: every mesh drawn was spawned for the request, and was ready to draw.
+#[allow(clippy::too_many_arguments)]
+fn check_frame(
+    capture: Option<Res<CurrentCapture>>,
+    scenes: Query<&DrawnScene>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    materials: Res<RenderMaterials2d<ColorMaterial>>,
+    material_instances: Res<RenderMaterial2dInstances<ColorMaterial>>,
+    mesh_instances: Res<RenderMesh2dInstances>,
+    phases: Query<&RenderPhase<Transparent2d>>,
+) {
+    let Some(capture) = capture else {
+        return;
+    };
+    let attempt = &capture.0;
+
+    // Frames of the last scene, or with meshes that aren't tagged yet, don't count.
+    let is_scene = !material_instances.is_empty()
+        && material_instances.keys().all(|entity| {
+            scenes
+                .get(*entity)
+                .is_ok_and(|scene| scene.0 == attempt.request)
+        });
+    // Meshes without their mesh or material aren't queued, so check for those first.
+    let queued = material_instances.iter().all(|(entity, material)| {
+        materials.contains_key(material)
+            && mesh_instances
+                .get(entity)
+                .is_some_and(|mesh| meshes.get(mesh.mesh_asset_id).is_some())
+    });
+    // Then the queued ones need their pipelines compiled.
+    let drawable = !phases.is_empty()
+        && phases
+            .iter()
+            .flat_map(|phase| &phase.items)
+            .all(|item| pipeline_cache.get_render_pipeline(item.pipeline).is_some());
+
+    attempt.check(is_scene && queued && drawable);
+}
+
+/// Copy the frame that was just drawn out of the offscreen target, for the current
+/// attempt. Like screenshots, the copy finishes a frame or two later.
+fn read_back_frame(
+    capture: Option<Res<CurrentCapture>>,
+    offscreen: Option<Res<OffscreenTarget>>,
+    images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let (Some(capture), Some(offscreen)) = (capture, offscreen) else {
+        return;
+    };
+    let attempt = capture.0.clone();
+    if attempt.read_back.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    // Don't bother copying frames we won't send.
+    if !attempt.complete() {
+        attempt.finish(CaptureOutcome::Incomplete);
+        return;
+    }
+    let Some(target) = images.get(&offscreen.0) else {
+        attempt.finish(CaptureOutcome::Incomplete);
+        return;
+    };
+
+    let size = Extent3d {
+        width: target.size.x as u32,
+        height: target.size.y as u32,
+        depth_or_array_layers: 1,
+    };
+    let format = target.texture_format;
+    let row_bytes = size.width as usize * format.pixel_size();
+    // Rows are copied to buffers at multiples of 256 bytes.
+    let padded_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes);
+
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("frame_read_back_buffer"),
+        size: (padded_row_bytes * size.height as usize) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("frame_read_back"),
+    });
+    encoder.copy_texture_to_buffer(
+        target.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes as u32),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+    // This is submitted after the frame, so it copies the frame.
+    render_queue.submit([encoder.finish()]);
+
+    // The buffer is mapped once the copy is done, when the device is next polled.
+    let (mapped_sender, mapped) = oneshot::channel();
+    buffer.slice(..).map_async(MapMode::Read, move |result| {
+        mapped_sender.send(result).ok();
+    });
+    let read_back = async move {
+        match mapped.await {
+            Ok(Ok(())) => {},
+            Ok(Err(err)) => {
+                let err = RenderError::Capture(err.to_string());
+                attempt.finish(CaptureOutcome::Captured(Err(err)));
+                return;
+            },
+            Err(_) => {
+                let err = RenderError::Capture("The frame was never copied".to_string());
+                attempt.finish(CaptureOutcome::Captured(Err(err)));
+                return;
+            },
+        }
+        let data = buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks(padded_row_bytes)
+            .flat_map(|row| &row[..row_bytes])
+            .copied()
+            .collect();
+        buffer.unmap();
+        attempt.captured(Image::new(size, TextureDimension::D2, data, format));
+    };
+    AsyncComputeTaskPool::get().spawn(read_back).detach();
+}
+
+pub(crate) fn send_image(reply: ImageReply, png_data: Result<Vec<u8>, RenderError>) {
+    match reply.send(png_data) {
+        Ok(_) => eprintln!("Sent image data"),
+        Err(_) => eprintln!("Failed to send image data, nobody is waiting for it"),
+    }
+}
@@ -0,0 +1,210 @@
+//! Turning a ridgeline, one height sample per scene unit, in to the segments of a
+//! path. By default every sample is a vertex, which is exactly what the heights say
+//! but gives thousands of vertices per layer. Optionally we instead simplify the
+//! ridgeline with Douglas-Peucker and fit a smooth curve through what is left, which
+//! is a much smaller path and reads as a softer, more painterly ridgeline.
+
+use bevy::math::Vec2;
+
+/// Samples at a multiple of this many scene units along are always kept, so the
+/// curve between two of them only depends on the samples in between. Otherwise what
+/// gets simplified away would change as the ridgeline scrolls, and it would wobble.
+/// It also means the curve only depends on the points as far as the next anchor but
+/// one, so a ridgeline that grows at one end only needs sampling again near that end.
+pub(crate) const ANCHOR_SPACING: f32 = 32.0;
+
+/// How to turn a ridgeline in to a path.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PathStyle {
+    /// A straight line to every height sample.
+    #[default]
+    Polyline,
+
+    /// Drop the samples that are within `tolerance` scene units of the simplified
+    /// ridgeline, then fit a Catmull-Rom curve through the rest. The curve can
+    /// overshoot sharp peaks by about as much again.
+    Smooth { tolerance: f32 },
+}
+
+impl PathStyle {
+    /// Smooth paths with the given tolerance, or the polyline if there isn't one.
+    pub fn from_tolerance(tolerance: Option<f32>) -> Self {
+        match tolerance {
+            Some(tolerance) => PathStyle::Smooth { tolerance },
+            None => PathStyle::Polyline,
+        }
+    }
+}
+
+/// A segment of a path, following on from where the previous one ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Segment {
+    Line(Vec2),
+    Cubic { ctrl1: Vec2, ctrl2: Vec2, to: Vec2 },
+}
+
+impl Segment {
+    /// Apply `f` to every point of the segment, e.g. to go from scene units to pixels.
+    pub fn map(self, f: impl Fn(Vec2) -> Vec2) -> Self {
+        match self {
+            Segment::Line(to) => Segment::Line(f(to)),
+            Segment::Cubic { ctrl1, ctrl2, to } => Segment::Cubic {
+                ctrl1: f(ctrl1),
+                ctrl2: f(ctrl2),
+                to: f(to),
+            },
+        }
+    }
+}
+
+/// Build the segments that run through the points of a ridgeline, starting from the
+/// first point. The points must be ordered by x.
+pub fn ridge_segments(points: &[Vec2], style: PathStyle) -> Vec<Segment> {
+    match style {
+        PathStyle::Polyline => points.iter().skip(1).copied().map(Segment::Line).collect(),
+        PathStyle::Smooth { tolerance } => catmull_rom(&simplify(points, tolerance)),
+    }
+}
+
+/// Sample the path through the points at the x of each point, like `ridge_segments`
+/// would draw it, in to `samples`. This is how the live view draws smooth paths, as
+/// a column per sample like the polyline.
+pub fn sample_ridge(points: &[Vec2], style: PathStyle, samples: &mut Vec<f32>) {
+    samples.clear();
+    let Some(first) = points.first() else {
+        return;
+    };
+    samples.push(first.y);
+
+    let mut from = *first;
+    let mut remaining = points[1..].iter();
+    for segment in ridge_segments(points, style) {
+        let to = match segment {
+            Segment::Line(to) | Segment::Cubic { to, .. } => to,
+        };
+        for point in remaining.by_ref() {
+            // The control points are a third of the way along in x, so x is linear
+            // in t along the curve.
+            let t = (point.x - from.x) / (to.x - from.x);
+            let y = match segment {
+                Segment::Line(_) => from.y + (to.y - from.y) * t,
+                Segment::Cubic { ctrl1, ctrl2, .. } => {
+                    let u = 1.0 - t;
+                    u * u * u * from.y
+                        + 3.0 * u * u * t * ctrl1.y
+                        + 3.0 * u * t * t * ctrl2.y
+                        + t * t * t * to.y
+                },
+            };
+            samples.push(y);
+            if point.x >= to.x {
+                break;
+            }
+        }
+        from = to;
+    }
+}
+
+/// Douglas-Peucker: keep the point furthest from the line between the ends if it is
+/// further than the tolerance, then do the same either side of it. The ends and the
+/// anchors are always kept.
+fn simplify(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let last = points.len() - 1;
+    let keep_always =
+        |i: usize| i == 0 || i == last || points[i].x.rem_euclid(ANCHOR_SPACING) == 0.0;
+    let mut keep: Vec<bool> = (0..points.len()).map(keep_always).collect();
+
+    // Use a stack rather than recursion, a flat ridgeline would otherwise recurse
+    // once per sample.
+    let anchors: Vec<usize> = (0..points.len()).filter(|i| keep[*i]).collect();
+    let mut stack: Vec<(usize, usize)> = anchors.windows(2).map(|w| (w[0], w[1])).collect();
+    while let Some((start, end)) = stack.pop() {
+        let (a, b) = (points[start], points[end]);
+        let furthest = (start + 1..end)
+            .map(|i| (i, distance_to_line(points[i], a, b)))
+            .max_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+        if let Some((i, distance)) = furthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((start, i));
+                stack.push((i, end));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+fn distance_to_line(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let line = b - a;
+    let length = line.length();
+    if length == 0.0 {
+        return point.distance(a);
+    }
+    line.perp_dot(point - a).abs() / length
+}
+
+/// Fit a curve through the points as cubic Beziers. The ridgeline is a function of
+/// x, so rather than the usual uniform Catmull-Rom, which can loop back on itself
+/// when the points are unevenly spaced like they are after simplifying, we take the
+/// slope at each point from its neighbours and place the control points a third of
+/// the way along in x. That way the curve never doubles back.
+fn catmull_rom(points: &[Vec2]) -> Vec<Segment> {
+    if points.len() < 3 {
+        return points.iter().skip(1).copied().map(Segment::Line).collect();
+    }
+
+    let slope = |i: usize| {
+        let before = points[i.saturating_sub(1)];
+        let after = points[(i + 1).min(points.len() - 1)];
+        (after.y - before.y) / (after.x - before.x)
+    };
+
+    points
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let (from, to) = (pair[0], pair[1]);
+            let third = (to.x - from.x) / 3.0;
+            Segment::Cubic {
+                ctrl1: from + Vec2::new(third, slope(i) * third),
+                ctrl2: to - Vec2::new(third, slope(i + 1) * third),
+                to,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ridge(len: usize) -> Vec<Vec2> {
+        (0..len)
+            .map(|x| Vec2::new(x as f32, (x as f32 * 0.37).sin() * 20.0 + (x % 7) as f32))
+            .collect()
+    }
+
+    #[test]
+    fn sampling_from_an_anchor_matches_past_the_next_one() {
+        let style = PathStyle::Smooth { tolerance: 1.0 };
+        let points = ridge(300);
+        let mut whole = Vec::new();
+        sample_ridge(&points, style, &mut whole);
+
+        let spacing = ANCHOR_SPACING as usize;
+        let start = 2 * spacing;
+        let mut part = Vec::new();
+        sample_ridge(&points[start..], style, &mut part);
+
+        assert_eq!(part[spacing..], whole[start + spacing..]);
+    }
+}
@@ -0,0 +1,85 @@
+//! Spawning the haze bands of the fog. The fog's effect on the mountains themselves
+//! is baked into the layer colors, so this is all there is to draw.
+//!
+//! The bands are gradients, which lyon shapes can't do, so they are plain meshes with
+//! a color per vertex instead.
+
+use super::{FogSpec, SCENE_SIZE};
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    sprite::MaterialMesh2dBundle,
+    window::WindowResolution,
+};
+
+/// How many rows the gradient of a haze band is split in to. The opacity is sampled
+/// at the edge of each row and interpolated in between.
+const HAZE_ROWS: usize = 32;
+
+/// Marks the haze bands, so they can be despawned with the mountains.
+#[derive(Component)]
+pub(crate) struct HazeBandElement;
+
+pub(crate) fn spawn_fog(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    fog: &FogSpec,
+    resolution: &WindowResolution,
+) {
+    let scale = resolution.width() / SCENE_SIZE;
+    // The vertex colors are multiplied by the material color, so leave it white.
+    let material = materials.add(ColorMaterial::default());
+
+    for band in &fog.haze_bands {
+        let colors = (0..=HAZE_ROWS)
+            .map(|row| {
+                let altitude = row as f32 / HAZE_ROWS as f32 * SCENE_SIZE;
+                fog.color.a * fog.haze_alpha(band, altitude)
+            })
+            .map(|alpha| Color::from(fog.color).with_a(alpha))
+            .collect::<Vec<_>>();
+
+        let mesh = vertical_gradient(resolution.width(), SCENE_SIZE * scale, &colors);
+        let transform = Transform::from_xyz(0.0, -resolution.height() / 2.0, band.z);
+
+        commands.spawn((HazeBandElement, MaterialMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: material.clone(),
+            transform,
+            ..default()
+        }));
+    }
+}
+
+/// Build a rectangle centered horizontally on the origin and going up from it, with
+/// the given colors spaced evenly from the bottom to the top.
+pub(crate) fn vertical_gradient(width: f32, height: f32, colors: &[Color]) -> Mesh {
+    let rows = colors.len() - 1;
+
+    let mut positions = Vec::new();
+    let mut vertex_colors = Vec::new();
+    for (i, color) in colors.iter().enumerate() {
+        let y = i as f32 / rows as f32 * height;
+        positions.push([-width / 2.0, y, 0.0]);
+        positions.push([width / 2.0, y, 0.0]);
+        vertex_colors.push(color.as_linear_rgba_f32());
+        vertex_colors.push(color.as_linear_rgba_f32());
+    }
+
+    // Two triangles per row.
+    let mut indices = Vec::new();
+    for row in 0..rows as u32 {
+        let bottom_left = row * 2;
+        let (bottom_right, top_left, top_right) =
+            (bottom_left + 1, bottom_left + 2, bottom_left + 3);
+        indices.extend([bottom_left, bottom_right, top_right]);
+        indices.extend([bottom_left, top_right, top_left]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, vertex_colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
@@ -0,0 +1,251 @@
+//! Each collection's art comes from an `ArtGenerator`. Everything that turns a token
+//! in to art, the Bevy app, the API and the processor, goes through one, so a new
+//! summit with different art only needs a new generator added to the registry.
+
+#[cfg(feature = "animation")]
+use super::{animate_scene, AnimationOptions};
+use super::{
+    despawn_scene, generate_scene, spawn_mountains, update_clouds, update_mountains,
+    GeneratorVersion, PaletteSet, TokenAttributes,
+};
+#[cfg(feature = "raster")]
+use super::{rasterize_scene, PathStyle};
+#[cfg(feature = "svg")]
+use super::{scene_to_svg, SvgOptions};
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+#[cfg(feature = "raster")]
+use image::RgbaImage;
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+/// The name of the generator used when none is given, and by the routes and configs
+/// that predate there being more than one.
+pub const DEFAULT_GENERATOR: &str = "summits";
+
+/// The art for a collection. The same token address and version must always give
+/// the same art, whichever of these methods draws it.
+pub trait ArtGenerator: Send + Sync {
+    /// The name the generator is registered and selected by.
+    fn name(&self) -> &'static str;
+
+    /// The width (and height) the art is published at, in pixels.
+    fn default_width(&self) -> u32;
+
+    /// Add the systems that animate the generator's scenes to the app.
+    fn build(&self, app: &mut App);
+
+    /// Generate the scene for the token and spawn it, with a camera.
+    fn spawn(
+        &self,
+        world: &mut World,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+    );
+
+    /// Despawn everything `spawn` spawned, other than the camera.
+    fn despawn(&self, world: &mut World);
+
+    /// The token's traits, for its NFT metadata.
+    fn attributes(
+        &self,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+    ) -> TokenAttributes;
+
+    /// Draw the art with the software rasterizer.
+    #[cfg(feature = "raster")]
+    fn rasterize(
+        &self,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+        width: u32,
+        path_style: PathStyle,
+    ) -> RgbaImage;
+
+    /// Export the art as an SVG, if the generator can.
+    #[cfg(feature = "svg")]
+    fn svg(
+        &self,
+        _token_address: &str,
+        _version: GeneratorVersion,
+        _palettes: &PaletteSet,
+        _options: &SvgOptions,
+    ) -> Option<String> {
+        None
+    }
+
+    /// Draw the frames of a looping animation of the art, if the generator can. The
+    /// frames are drawn as they are iterated over.
+    #[cfg(feature = "animation")]
+    fn animate(
+        &self,
+        _token_address: &str,
+        _version: GeneratorVersion,
+        _palettes: &PaletteSet,
+        _options: &AnimationOptions,
+    ) -> Option<Box<dyn Iterator<Item = RgbaImage>>> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn ArtGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ArtGenerator({})", self.name())
+    }
+}
+
+/// The generators we can select from, by name.
+#[derive(Clone, Debug)]
+pub struct GeneratorRegistry {
+    generators: BTreeMap<&'static str, Arc<dyn ArtGenerator>>,
+}
+
+impl Default for GeneratorRegistry {
+    /// All of the generators built in to artcore.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Summits);
+        registry
+    }
+}
+
+impl GeneratorRegistry {
+    pub fn empty() -> Self {
+        Self {
+            generators: BTreeMap::new(),
+        }
+    }
+
+    /// Add a generator, replacing any with the same name.
+    pub fn register(&mut self, generator: impl ArtGenerator + 'static) {
+        self.generators
+            .insert(generator.name(), Arc::new(generator));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ArtGenerator>> {
+        self.generators.get(name).cloned()
+    }
+
+    /// Like `get`, but with an error naming the generators there are.
+    pub fn find(&self, name: &str) -> Result<Arc<dyn ArtGenerator>, UnknownGenerator> {
+        self.get(name).ok_or_else(|| UnknownGenerator {
+            name: name.to_string(),
+            known: self.names().map(str::to_string).collect(),
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.generators.keys().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn ArtGenerator>> {
+        self.generators.values()
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownGenerator {
+    pub name: String,
+    pub known: Vec<String>,
+}
+
+impl fmt::Display for UnknownGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown generator {}, expected one of: {}",
+            self.name,
+            self.known.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownGenerator {}
+
+/// The scrolling mountains of the Aptos Ecosystem Summit.
+#[derive(Clone, Copy, Debug)]
+pub struct Summits;
+
+impl ArtGenerator for Summits {
+    fn name(&self) -> &'static str {
+        DEFAULT_GENERATOR
+    }
+
+    fn default_width(&self) -> u32 {
+        2000
+    }
+
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (update_mountains, update_clouds));
+    }
+
+    fn spawn(
+        &self,
+        world: &mut World,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+    ) {
+        let scene = generate_scene(token_address, version, palettes);
+        world.run_system_once_with(scene, spawn_mountains);
+    }
+
+    fn despawn(&self, world: &mut World) {
+        world.run_system_once(despawn_scene);
+    }
+
+    fn attributes(
+        &self,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+    ) -> TokenAttributes {
+        TokenAttributes::from_scene(&generate_scene(token_address, version, palettes))
+    }
+
+    #[cfg(feature = "raster")]
+    fn rasterize(
+        &self,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+        width: u32,
+        path_style: PathStyle,
+    ) -> RgbaImage {
+        rasterize_scene(
+            &generate_scene(token_address, version, palettes),
+            width,
+            path_style,
+        )
+    }
+
+    #[cfg(feature = "svg")]
+    fn svg(
+        &self,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+        options: &SvgOptions,
+    ) -> Option<String> {
+        Some(scene_to_svg(
+            &generate_scene(token_address, version, palettes),
+            options,
+        ))
+    }
+
+    #[cfg(feature = "animation")]
+    fn animate(
+        &self,
+        token_address: &str,
+        version: GeneratorVersion,
+        palettes: &PaletteSet,
+        options: &AnimationOptions,
+    ) -> Option<Box<dyn Iterator<Item = RgbaImage>>> {
+        Some(Box::new(animate_scene(
+            generate_scene(token_address, version, palettes),
+            options,
+        )))
+    }
+}
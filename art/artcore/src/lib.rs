@@ -4,52 +4,226 @@
 // See this note on portability for something explaining why the results are different
 // on native MacOS vs WASM vs etc: https://github.com/rust-random/rand/issues/1415.
 
+#[cfg(feature = "animation")]
+mod animation;
 #[cfg(feature = "api")]
 mod api;
+mod attributes;
+mod blend;
+#[cfg(feature = "api")]
+mod capture;
+mod curves;
+mod fog;
+mod generator;
+mod offscreen;
+mod overlay_pass;
+mod overlays;
+mod plugin;
+#[cfg(feature = "raster")]
+mod raster;
+#[cfg(feature = "raster")]
+mod render;
+mod scene;
+#[cfg(feature = "api")]
+mod service;
+mod sky;
+#[cfg(feature = "svg")]
+mod svg;
 
+#[cfg(feature = "animation")]
+pub use animation::*;
 #[cfg(feature = "api")]
 pub use api::*;
+pub use attributes::*;
+#[cfg(feature = "window")]
+use bevy::winit::WinitPlugin;
 use bevy::{
-    core_pipeline::clear_color::ClearColorConfig, ecs::system::RunSystemOnce, prelude::*,
-    window::WindowResolution,
+    app::ScheduleRunnerPlugin,
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
+    },
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    window::{PrimaryWindow, WindowResolution},
 };
-use bevy_prototype_lyon::prelude::*;
+pub use blend::*;
 use clap::Parser;
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha8Rng as MyRng;
-use sha2::{Digest, Sha256};
-use std::ops::Range;
+pub use curves::*;
+use fog::{spawn_fog, HazeBandElement};
+pub use generator::*;
+pub use offscreen::*;
+pub use overlay_pass::*;
+pub use overlays::*;
+pub use plugin::*;
+#[cfg(feature = "raster")]
+pub use raster::*;
+#[cfg(feature = "raster")]
+pub use render::*;
+pub use scene::*;
+#[cfg(feature = "api")]
+pub use service::*;
+use sky::{spawn_sky, update_clouds, SkyElement};
+#[cfg(feature = "api")]
+use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+#[cfg(feature = "svg")]
+pub use svg::*;
 
 // TODO: Make the clap stuff conditional behind a feature.
 #[derive(Clone, Debug, Parser)]
 pub struct AppConfig {
-    // TODO: Make the generation of the mountains unaffected by the width.
-    #[clap(long, default_value_t = 2000.)]
-    pub width: f32,
+    /// The width (and height) of the window. This only changes the resolution the
+    /// art is drawn at, not what it looks like. Defaults to the width the generator's
+    /// art is published at.
+    #[clap(long)]
+    pub width: Option<f32>,
+
+    /// The name of the generator to draw the art with.
+    #[clap(long, default_value = DEFAULT_GENERATOR)]
+    pub generator: String,
 
     // TODO: If we can make the Rust SDK less massive, use AccountAddress instead.
     #[clap(long)]
     pub initial_token_address: String,
 
+    /// The version of the generation algorithm to use.
+    #[clap(long, value_enum)]
+    pub generator_version: GeneratorVersion,
+
+    /// A YAML file with the palette set to pick colors from. If not given we use the
+    /// default palette set built in to the binary.
+    #[clap(long)]
+    pub palettes: Option<PathBuf>,
+
+    /// Smooth the ridgelines by fitting curves through them, dropping the height
+    /// samples that are within this many scene units of the ridgeline.
+    #[clap(long)]
+    pub smooth_paths: Option<f32>,
+
+    /// A YAML file declaring the overlays to draw over the art, in the window and over
+    /// the images the API sends back. If not given we use the ones at
+    /// `DEFAULT_OVERLAYS`: for the window, the ones in this repo wherever it's run
+    /// from, or none if the repo isn't there.
+    #[clap(long)]
+    pub overlays: Option<PathBuf>,
+
     #[clap(long)]
     pub paused: bool,
+
+    /// Draw to an image instead of a window, so no display is needed. This isn't a
+    /// flag because only `RenderService` sets it, for the API and the processor,
+    /// which read the frames back; anywhere else there would be nothing to see.
+    #[clap(skip)]
+    pub offscreen: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct WebConfig {
     pub html_canvas_id: String,
+
+    /// There is no filesystem in the browser, so the overlays are given rather than
+    /// loaded from `AppConfig::overlays`.
+    pub overlays: OverlaySources,
 }
 
-#[derive(Resource)]
-pub struct AppSeed {
-    pub token_address: String,
+/// Config the app can't be built with.
+#[derive(Debug)]
+pub enum ConfigError {
+    Palettes(PaletteError),
+    Generator(UnknownGenerator),
+    Overlays(OverlayError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Palettes(err) => write!(f, "{}", err),
+            ConfigError::Generator(err) => write!(f, "{}", err),
+            ConfigError::Overlays(err) => write!(f, "{}", err),
+        }
+    }
 }
 
+impl std::error::Error for ConfigError {}
+
 impl AppConfig {
-    pub fn build(self, web_config: Option<WebConfig>) -> App {
+    pub fn build(self, web_config: Option<WebConfig>) -> Result<App, ConfigError> {
+        let overlays = match (&web_config, &self.overlays) {
+            (Some(web_config), _) => web_config.overlays.clone(),
+            (None, Some(path)) => OverlaySources::load(path).map_err(ConfigError::Overlays)?,
+            // The CLI is run from anywhere, so we look for the default overlays next to
+            // the source rather than in the working directory.
+            (None, None) => {
+                let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("..")
+                    .join(DEFAULT_OVERLAYS);
+                OverlaySources::load(&path).unwrap_or_default()
+            },
+        };
+        let plugin = SummitsPlugin {
+            overlays: Some(overlays),
+            ..self.plugin()?
+        };
+        Ok(self.app(plugin, web_config))
+    }
+
+    /// The plugin for `RenderService`, which renders the art the API asks for. This
+    /// loads everything up front, so bad config fails before the app is built.
+    #[cfg(feature = "api")]
+    pub(crate) fn api_plugin(
+        &self,
+        api_channels: ApiChannels,
+    ) -> Result<SummitsPlugin, ConfigError> {
+        let overlays =
+            Overlays::load_or_default(self.overlays.as_deref()).map_err(ConfigError::Overlays)?;
+        // We draw the overlays over the images we capture instead of in the window,
+        // so they are drawn exactly like the images the software rasterizer draws.
+        Ok(SummitsPlugin {
+            offscreen: self.offscreen,
+            api: Some(ApiOptions {
+                channels: api_channels,
+                overlays: Arc::new(overlays),
+            }),
+            ..self.plugin()?
+        })
+    }
+
+    /// The plugin drawing the art, without overlays. Bad config fails here rather
+    /// than after we open a window.
+    fn plugin(&self) -> Result<SummitsPlugin, ConfigError> {
+        let palettes =
+            PaletteSet::load_or_default(self.palettes.as_deref()).map_err(ConfigError::Palettes)?;
+        let generators = GeneratorRegistry::default();
+        let generator = generators
+            .find(&self.generator)
+            .map_err(ConfigError::Generator)?;
+        Ok(SummitsPlugin {
+            generator,
+            generators,
+            palettes,
+            path_style: PathStyle::from_tolerance(self.smooth_paths),
+            paused: self.paused,
+            ..SummitsPlugin::new(self.initial_token_address.clone(), self.generator_version)
+        })
+    }
+
+    /// An app with a window to draw the art in, with the plugin added. If the plugin
+    /// draws offscreen the window is never opened.
+    pub(crate) fn app(&self, plugin: SummitsPlugin, web_config: Option<WebConfig>) -> App {
         let mut app = App::new();
 
-        let resolution = WindowResolution::new(self.width, self.width);
+        let width = self
+            .width
+            .unwrap_or_else(|| plugin.generator.default_width() as f32);
+        let resolution = WindowResolution::new(width, width);
         let window = match web_config {
             Some(web_config) => Window {
                 resolution,
@@ -63,65 +237,33 @@ impl AppConfig {
                 ..default()
             },
         };
-        app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        let default_plugins = DefaultPlugins.set(WindowPlugin {
             primary_window: Some(window),
             ..default()
-        }));
-
-        app.insert_resource(AppSeed {
-            token_address: self.initial_token_address,
-        })
-        .insert_resource(PauseState {
-            paused: self.paused,
-        })
-        .add_plugins(ShapePlugin)
-        .add_systems(Startup, initial_spawn)
-        .add_systems(Update, handle_keys)
-        .add_systems(Update, update_mountains);
-
-        app
-    }
-
-    #[cfg(feature = "api")]
-    pub fn build_for_api(self, web_config: Option<WebConfig>, api_channels: ApiChannels) -> App {
-        let mut app = self.build(web_config);
-        app.insert_resource(api_channels.image_channel)
-            .insert_resource(api_channels.token_address_receiver)
-            .add_systems(Update, token_address_listener);
+        });
+        if plugin.offscreen {
+            // Winit would open the window, and drive the app. Without it we drive the
+            // app ourselves, at the frame rate a window would have.
+            #[cfg(feature = "window")]
+            let default_plugins = default_plugins.disable::<WinitPlugin>();
+            app.add_plugins((
+                default_plugins,
+                ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+            ));
+        } else {
+            // So `RenderService` can run the app on a thread of its own.
+            #[cfg(feature = "window")]
+            app.add_plugins(default_plugins.set(WinitPlugin {
+                run_on_any_thread: true,
+            }));
+            #[cfg(not(feature = "window"))]
+            panic!("Drawing in a window needs artcore's window feature");
+        }
+        app.add_plugins(plugin);
         app
     }
 }
 
-#[allow(dead_code)]
-fn rand_color(rng: &mut MyRng, r: Range<u8>, g: Range<u8>, b: Range<u8>) -> Color {
-    Color::rgb_u8(rng.gen_range(r), rng.gen_range(g), rng.gen_range(b))
-}
-
-fn interpolate(left: Color, right: Color, left_weight: f32) -> Color {
-    let right_weight = 1.0 - left_weight;
-
-    let red = left.r() * left_weight + right.r() * right_weight;
-    let green = left.g() * left_weight + right.g() * right_weight;
-    let blue = left.b() * left_weight + right.b() * right_weight;
-    let alpha = left.a() * left_weight + right.a() * right_weight;
-
-    Color::rgba(red, green, blue, alpha)
-}
-
-fn get_rng(token_address: &str) -> MyRng {
-    // Convert the token address into a u64 for the seed.
-    let mut hasher = Sha256::new();
-    hasher.update(&token_address);
-    let result = hasher.finalize();
-    let first_eight_bytes = &result[0..8];
-    let seed = u64::from_be_bytes(first_eight_bytes.try_into().unwrap());
-
-    info!("Token address: {} // Seed {}", token_address, seed);
-
-    // Build deterministic rng with seed.
-    MyRng::seed_from_u64(seed)
-}
-
 // This is not Clone on purpose, we only want to use one randomness.
 #[derive(Resource)]
 struct Randomness {
@@ -129,122 +271,93 @@ struct Randomness {
 }
 
 impl Randomness {
-    pub fn from_token_address(token_address: &str) -> Self {
-        Randomness {
-            rng: get_rng(token_address),
-        }
+    /// Pick up the RNG from where generating the scene left it.
+    pub fn resume(scene: &SceneSpec) -> Self {
+        let mut rng = get_rng(scene.seed);
+        rng.set_word_pos(scene.rng_word_pos);
+        Randomness { rng }
     }
 }
 
-fn initial_spawn(mut commands: Commands, app_seed: Res<AppSeed>) {
-    commands.insert_resource(Randomness::from_token_address(&app_seed.token_address));
-    commands.add(move |world: &mut World| {
-        world.run_system_once(spawn_mountains);
-    });
-}
-
-// TODO: Move this to to an update system and scroll each mountain layer.
 fn spawn_mountains(
+    In(scene): In<SceneSpec>,
     mut commands: Commands,
-    window: Query<&Window>,
-    mut randomness: ResMut<Randomness>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    app_seed: Res<AppSeed>,
+    offscreen: Option<Res<OffscreenTarget>>,
 ) {
-    let rng = &mut randomness.rng;
-
-    // Generate sky color.
-    let sky_colors = [
-        Color::rgb_u8(255, 202, 140),
-        Color::rgb_u8(211, 255, 154),
-        Color::rgb_u8(71, 224, 226),
-    ];
-    let sky_color = sky_colors[rng.gen_range(0..sky_colors.len())];
-
-    info!("Sky color: {:?}", sky_color);
-
-    // Generate fog color.
-    // let fog_color = rand_color(&mut rng, 1..255, 1..255, 1..255);
+    let window = window.single();
 
-    // TODO: Add stuff in sky.
+    commands.insert_resource(Randomness::resume(&scene));
 
     // Spawn the camera with our sky color as the background
+    let target = match offscreen {
+        Some(offscreen) => RenderTarget::Image(offscreen.0.clone()),
+        None => RenderTarget::default(),
+    };
     commands.spawn(Camera2dBundle {
+        camera: Camera {
+            target,
+            ..default()
+        },
         camera_2d: Camera2d {
-            clear_color: ClearColorConfig::Custom(sky_color),
+            clear_color: ClearColorConfig::Custom(scene.palette.sky.into()),
         },
         ..default()
     });
 
-    let window = window.single();
-    let height = window.resolution.height() as f64;
-
-    // Get mountain color.
-    let mountain_colors = [
-        Color::rgb_u8(129, 128, 85),
-        Color::rgb_u8(167, 154, 93),
-        Color::rgb_u8(191, 182, 129),
-        Color::rgb_u8(230, 230, 230),
-        Color::rgb_u8(132, 134, 135),
-    ];
-
-    let mountain_base_color = mountain_colors[rng.gen_range(0..mountain_colors.len())];
-
-    // Generate mountains back to front.
-    let mut mountains = Vec::new();
-    let num_mountains: u64 = rng.gen_range(4..7);
-    let base_max_height = height * 0.7;
-    // If this is close to 0, the heights of the mountains will be more similar.
-    let height_diff_multiplier = 0.7;
-    for i in 0..num_mountains {
-        let color = interpolate(
-            mountain_base_color,
-            sky_color,
-            (i + 1) as f32 / num_mountains as f32,
-        );
-        let min_height = -height * 2.0 / (num_mountains * (num_mountains - i)) as f64;
-
-        // Scale max_height based on z-order.
-        let max_height =
-            base_max_height * (1.0 - (i as f64 / num_mountains as f64 * height_diff_multiplier));
-        info!(
-            "Mountain {} min height {} max height: {}",
-            i, min_height, max_height
+    // Spawn everything in the sky, behind the mountains.
+    spawn_sky(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &scene.sky,
+        &window.resolution,
+    );
+
+    // Spawn the haze bands, they go between the mountains.
+    if let Some(fog) = &scene.fog {
+        spawn_fog(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            fog,
+            &window.resolution,
         );
-
-        let mountain = Mountain::new(
-            window.width() as u32,
-            min_height,
-            max_height,
-            color,
-            (i + 1) as f32,
-            &mut randomness,
-        );
-        mountains.push(mountain);
     }
 
     // Spawn and draw mountains.
-    for mountain in mountains {
-        mountain.spawn(&mut commands, &window.resolution);
+    for layer in scene.layers {
+        Mountain::from_layer(layer).spawn(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &window.resolution,
+            app_seed.path_style,
+        );
     }
 }
 
-#[derive(Resource)]
-struct PauseState {
-    paused: bool,
-}
+/// Everything we spawn for a scene, other than the camera.
+type SceneEntities = Or<(With<Mountain>, With<SkyElement>, With<HazeBandElement>)>;
 
-fn handle_keys(keyboard_input: Res<Input<KeyCode>>, mut pause_state: ResMut<PauseState>) {
-    if keyboard_input.just_pressed(KeyCode::P) {
-        pause_state.paused = !pause_state.paused;
-        info!("Paused: {}", pause_state.paused);
+// This despawns the shapes drawn too.
+fn despawn_scene(mut commands: Commands, entities: Query<Entity, SceneEntities>) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 }
 
 fn update_mountains(
     time: Res<Time>,
-    window: Query<&Window>,
+    window: Query<&Window, With<PrimaryWindow>>,
     mut randomness: ResMut<Randomness>,
-    mut query: Query<(&mut Mountain, &mut Path)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(&mut Mountain, &mut Transform, &Mesh2dHandle)>,
     pause_state: Res<PauseState>,
+    app_seed: Res<AppSeed>,
 ) {
     if pause_state.paused {
         return;
@@ -253,185 +366,356 @@ fn update_mountains(
     let window = window.single();
     let resolution = &window.resolution;
 
-    for (mut mountain, mut path) in query.iter_mut() {
+    for (mut mountain, mut transform, mesh) in query.iter_mut() {
         // Scroll the heights of the mountain.
-        mountain.scroll(time.delta_seconds(), &mut randomness);
-
-        // Update the path.
-        *path = mountain.build_path(&resolution);
-    }
-}
-
-struct MountainHeightGenerator {
-    height: f32,
-    slope: f32,
-    step_max: f32,
-    step_change: f32,
-    min_height: f32,
-    max_height: f32,
-}
+        let scrolled = mountain.scroll(time.delta_seconds(), &mut randomness);
 
-impl MountainHeightGenerator {
-    fn new(min_height: f32, max_height: f32, randomness: &mut ResMut<Randomness>) -> Self {
-        let rng = &mut randomness.rng;
-        let step_max = rng.gen_range(0.9..1.1);
-        let step_change = rng.gen_range(0.15..0.35);
-        let height = rng.gen_range(0.0..max_height);
-        let slope = rng.gen_range(0.0..step_max) * 2.0 - step_max;
-
-        MountainHeightGenerator {
-            height,
-            slope,
-            step_max,
-            step_change,
-            min_height,
-            max_height,
+        // Most frames we only scroll part of a pixel, which moving the mesh takes
+        // care of. Only when whole pixels scroll in does the mesh need updating.
+        if scrolled > 0 {
+            let mesh = meshes.get_mut(&mesh.0).unwrap();
+            mountain.update_mesh(mesh, scrolled, resolution, app_seed.path_style);
         }
+        transform.translation.x = mountain.translation_x(resolution);
     }
 }
 
-impl MountainHeightGenerator {
-    fn next(&mut self, randomness: &mut ResMut<Randomness>) -> Option<f32> {
-        self.height += self.slope;
-        self.slope += randomness.rng.gen_range(0.0..self.step_change) * 2.0 - self.step_change;
-
-        if self.slope > self.step_max {
-            self.slope = self.step_max;
-        } else if self.slope < -self.step_max {
-            self.slope = -self.step_max;
-        }
-
-        if self.height > self.max_height {
-            self.height = self.max_height;
-            self.slope *= -1.0;
-        } else if self.height < self.min_height {
-            self.height = self.min_height;
-            self.slope *= -1.0;
-        }
-
-        Some(self.height)
-    }
-}
-
+/// Once a mountain has scrolled this many columns past the column its mesh is
+/// measured from, we measure from the anchor at or before the first visible column
+/// again so the positions stay small enough to be precise as f32s. Measuring from an
+/// anchor keeps the anchors of smooth paths where they were.
+const REBASE_COLUMNS: u64 = 1 << 16;
+
+/// A mountain layer in the live view. It is drawn as a mesh with a column of two
+/// vertices per height, the top of the ridgeline and the bottom of the scene, and
+/// two triangles between each column and the next. The vertices are colored with the
+/// layer's shading at their altitude, which the GPU interpolates down each column.
+///
+/// Rather than rebuilding the mesh as the mountain scrolls, the mesh is a ring: the
+/// column `c` (counting every column since the mountain was spawned) lives in slot
+/// `c % heights.len()`, so the columns that scroll in on the right overwrite the
+/// ones that scrolled off on the left, and the mesh is moved left to match. Between
+/// the newest column and the oldest there are no triangles, that is the seam of the
+/// ring. That way the work per frame is proportional to how far we scrolled.
 #[derive(Component)]
 struct Mountain {
-    heights: Vec<f32>,
-    color: Color,
+    // Like the mesh, this is a ring, so scrolling never allocates.
+    heights: VecDeque<f32>,
+    color: SceneColor,
+    shade: Option<VerticalGradient>,
     z: f32,
-    height_generator: MountainHeightGenerator,
+    height_generator: HeightGenerator,
     // To ensure we can scroll smoothly we need to keep track of what fraction of the
-    // pixel we have scrolled through.
+    // pixel (scene unit) we have scrolled through.
     pub sub_pixel_offset: f32,
+    // The column the first of the heights is in.
+    first_column: u64,
+    // The column the x of the vertices is measured from.
+    origin: u64,
+    // Scratch space for sampling smooth paths, kept so scrolling doesn't allocate.
+    points: Vec<Vec2>,
+    samples: Vec<f32>,
 }
 
 impl Mountain {
-    pub fn new(
-        width: u32,
-        min_height: f64,
-        max_height: f64,
-        color: Color,
-        z: f32,
-        randomness: &mut ResMut<Randomness>,
-    ) -> Self {
-        // Initialize the height generator
-        let mut height_generator =
-            MountainHeightGenerator::new(min_height as f32, max_height as f32, randomness);
-
-        // Generate initial heights
-        let mut heights: Vec<f32> = Vec::new();
-        for _ in 0..width * 2 {
-            heights.push(height_generator.next(randomness).unwrap());
-        }
-
+    pub fn from_layer(layer: LayerSpec) -> Self {
         Mountain {
-            heights,
-            color,
-            z,
-            height_generator,
+            heights: layer.heights.into(),
+            color: layer.color,
+            shade: layer.shade,
+            z: layer.z,
+            height_generator: layer.generator,
             sub_pixel_offset: 0.0,
+            first_column: 0,
+            origin: 0,
+            points: Vec::new(),
+            samples: Vec::new(),
         }
     }
 
-    fn build_path(self: &Mountain, resolution: &WindowResolution) -> Path {
-        let mut path_builder = PathBuilder::new();
+    fn slot(&self, column: u64) -> usize {
+        (column % self.heights.len() as u64) as usize
+    }
+
+    fn newest_column(&self) -> u64 {
+        self.first_column + self.heights.len() as u64 - 1
+    }
+
+    fn build_mesh(&mut self, resolution: &WindowResolution, style: PathStyle) -> Mesh {
+        let slots = self.heights.len();
+
+        // Two triangles from each slot to the next, apart from at the seam.
+        let seam = self.slot(self.newest_column());
+        let indices = (0..slots)
+            .flat_map(|slot| ring_quad(slot, slots, slot == seam))
+            .collect();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0; 3]; 2 * slots]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, vec![[0.0; 4]; 2 * slots]);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        self.write_columns(&mut mesh, slots, resolution, style);
+        mesh
+    }
 
-        // Start in the bottom left corner with the sub_pixel_offset.
-        let start_x = -resolution.width() / 2. - self.sub_pixel_offset;
+    /// Update the mesh after `scrolled` columns scrolled in on the right.
+    fn update_mesh(
+        &mut self,
+        mesh: &mut Mesh,
+        scrolled: usize,
+        resolution: &WindowResolution,
+        style: PathStyle,
+    ) {
+        let slots = self.heights.len();
+
+        // Move the seam to after the new newest column.
+        if let Some(Indices::U32(indices)) = mesh.indices_mut() {
+            let old_seam = self.slot(self.newest_column() - scrolled as u64);
+            let seam = self.slot(self.newest_column());
+            set_ring_quad(indices, old_seam, slots, false);
+            set_ring_quad(indices, seam, slots, true);
+        }
 
-        path_builder.move_to(Vec2::new(start_x, -resolution.height() / 2.));
+        let columns = if self.first_column - self.origin >= REBASE_COLUMNS {
+            self.origin = self.first_column / ANCHOR_SPACING as u64 * ANCHOR_SPACING as u64;
+            slots
+        } else {
+            scrolled
+        };
+        self.write_columns(mesh, columns, resolution, style);
+    }
 
-        for (i, y) in self.heights.iter().enumerate() {
-            let x = start_x + i as f32;
-            let point = Vec2::new(x, *y);
-            path_builder.line_to(point);
+    /// Write the positions and colors of the newest `columns` columns in to the mesh.
+    fn write_columns(
+        &mut self,
+        mesh: &mut Mesh,
+        columns: usize,
+        resolution: &WindowResolution,
+        style: PathStyle,
+    ) {
+        // Take the attributes out so we can write both at once. This moves them, it
+        // doesn't copy them.
+        let (
+            Some(VertexAttributeValues::Float32x3(mut positions)),
+            Some(VertexAttributeValues::Float32x4(mut colors)),
+        ) = (
+            mesh.remove_attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.remove_attribute(Mesh::ATTRIBUTE_COLOR),
+        )
+        else {
+            panic!("Mountain meshes have positions and colors");
+        };
+        let mut points = std::mem::take(&mut self.points);
+        let mut samples = std::mem::take(&mut self.samples);
+
+        // Scale from scene units to pixels.
+        let scale = resolution.width() / SCENE_SIZE;
+        // The bottom of the window, given the mesh is moved down a third of it.
+        let bottom = -resolution.height() / 2.0 + resolution.height() / 3.0;
+        let bottom_color = self.color_at(0.0);
+        let mut write = |column: u64, height: f32| {
+            let slot = self.slot(column);
+            let x = (column - self.origin) as f32 * scale;
+            positions[2 * slot] = [x, height * scale, 0.0];
+            positions[2 * slot + 1] = [x, bottom, 0.0];
+            // Heights are measured from a baseline a sixth of the way up the scene.
+            colors[2 * slot] = self.color_at(height + SCENE_SIZE / 6.0);
+            colors[2 * slot + 1] = bottom_color;
+        };
+
+        match style {
+            PathStyle::Polyline => {
+                let columns = columns.min(self.heights.len());
+                let first_new = self.heights.len() - columns;
+                for (i, height) in self.heights.iter().enumerate().skip(first_new) {
+                    write(self.first_column + i as u64, *height);
+                }
+            },
+            // The curve through each height depends on the heights around it, but
+            // only as far as the anchors, so the new columns only change it from the
+            // anchor before the last one at or before the old newest column. To
+            // sample it exactly from there we start an anchor further back. We place
+            // the points at their column, measured from the origin like the vertices
+            // so they stay precise, so the anchors scroll with the mountain.
+            PathStyle::Smooth { .. } => {
+                let spacing = ANCHOR_SPACING as u64;
+                let newest = self.newest_column();
+                let first_new = newest + 1 - columns.min(self.heights.len()) as u64;
+                let anchor = first_new.saturating_sub(1) / spacing * spacing;
+                let first_changed = anchor.saturating_sub(spacing).max(self.first_column);
+                let start = anchor.saturating_sub(2 * spacing).max(self.first_column);
+
+                points.clear();
+                points.extend((start..=newest).map(|column| {
+                    let height = self.heights[(column - self.first_column) as usize];
+                    Vec2::new((column - self.origin) as f32, height)
+                }));
+                sample_ridge(&points, style, &mut samples);
+                let skip = (first_changed - start) as usize;
+                for (column, height) in (start..).zip(&samples).skip(skip) {
+                    write(column, *height);
+                }
+            },
         }
 
-        // End in the bottom right corner.
-        let end_x = start_x + self.heights.len() as f32;
-        path_builder.line_to(Vec2::new(end_x, -resolution.height() / 2.0));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        self.points = points;
+        self.samples = samples;
+    }
+
+    /// The layer's color at the given altitude, in scene units from the bottom of the
+    /// scene, as a vertex color.
+    fn color_at(&self, altitude: f32) -> [f32; 4] {
+        let color = match &self.shade {
+            Some(shade) => shade.color_at(altitude),
+            None => self.color,
+        };
+        Color::from(color).as_linear_rgba_f32()
+    }
 
-        path_builder.close();
-        path_builder.build()
+    /// Where the mesh goes so the first column is at the left edge, less how far we
+    /// have scrolled in to it.
+    fn translation_x(&self, resolution: &WindowResolution) -> f32 {
+        let scale = resolution.width() / SCENE_SIZE;
+        let columns = (self.first_column - self.origin) as f32 + self.sub_pixel_offset;
+        -resolution.width() / 2. - columns * scale
     }
 
-    pub fn spawn(self, commands: &mut Commands, resolution: &WindowResolution) {
-        let path = self.build_path(&resolution);
+    pub fn spawn(
+        mut self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+        resolution: &WindowResolution,
+        style: PathStyle,
+    ) {
+        let mesh = self.build_mesh(resolution, style);
 
         // Apply z transformation so the shapes are layered properly and move
         // everything down a bit.
-        let transform = Transform::from_xyz(0.0, -resolution.height() / 3.0, self.z);
+        let transform = Transform::from_xyz(
+            self.translation_x(resolution),
+            -resolution.height() / 3.0,
+            self.z,
+        );
 
-        let color = self.color;
+        // The vertex colors are multiplied by the material color, so leave it white.
+        let material = materials.add(ColorMaterial::default());
         commands.spawn(MountainBundle {
             mountain: self,
-            shape_bundle: ShapeBundle {
-                path,
-                spatial: SpatialBundle::from_transform(transform),
+            mesh_bundle: MaterialMesh2dBundle {
+                mesh: meshes.add(mesh).into(),
+                material,
+                transform,
                 ..default()
             },
-            fill: Fill::color(color),
         });
     }
 
-    pub fn scroll(&mut self, delta_seconds: f32, randomness: &mut ResMut<Randomness>) {
+    /// Scroll the mountain, returning how many whole pixels (scene units) scrolled in.
+    pub fn scroll(&mut self, delta_seconds: f32, randomness: &mut Randomness) -> usize {
         let movement = self.speed() * delta_seconds;
         self.sub_pixel_offset += movement;
 
         let whole_pixels = self.sub_pixel_offset.floor() as usize;
         self.sub_pixel_offset -= whole_pixels as f32;
 
-        // Remove points from the left.
-        self.heights.drain(0..whole_pixels);
-
-        // Add points to the right.
+        // Remove points from the left and add them to the right.
         for _ in 0..whole_pixels {
+            self.heights.pop_front();
             self.heights
-                .push(self.height_generator.next(randomness).unwrap());
+                .push_back(self.height_generator.next(&mut randomness.rng).unwrap());
         }
+        self.first_column += whole_pixels as u64;
+
+        whole_pixels
     }
 
     pub fn speed(&self) -> f32 {
-        let exponent = 2.0;
-        let base_speed = 1.2;
-        base_speed * self.z.powf(exponent)
+        parallax_speed(self.z)
+    }
+}
+
+/// The indices of the two triangles from the given slot of a ring mesh to the next.
+/// At the seam they are all the same vertex, so nothing is drawn.
+fn ring_quad(slot: usize, slots: usize, seam: bool) -> [u32; 6] {
+    if seam {
+        return [0; 6];
     }
+    let (top, bottom) = (2 * slot as u32, 2 * slot as u32 + 1);
+    let next = (slot + 1) % slots;
+    let (next_top, next_bottom) = (2 * next as u32, 2 * next as u32 + 1);
+    [top, bottom, next_bottom, top, next_bottom, next_top]
+}
+
+fn set_ring_quad(indices: &mut [u32], slot: usize, slots: usize, seam: bool) {
+    indices[6 * slot..6 * slot + 6].copy_from_slice(&ring_quad(slot, slots, seam));
+}
+
+/// How fast something at the given z scrolls, in scene units per second. Things
+/// further back scroll slower.
+pub(crate) fn parallax_speed(z: f32) -> f32 {
+    let exponent = 2.0;
+    let base_speed = 1.2;
+    base_speed * z.powf(exponent)
 }
 
 #[derive(Bundle)]
 struct MountainBundle {
     mountain: Mountain,
-    shape_bundle: ShapeBundle,
-    fill: Fill,
+    mesh_bundle: MaterialMesh2dBundle<ColorMaterial>,
 }
 
-/*
-fn exiter(channel: Res<ImageChannel>, mut exit: EventWriter<AppExit>) {
-    if channel.receiver.is_full() {
-        eprintln!("Channel has image data, exiting Bevy app");
-        // TODO: This doesn't work properly.
-        // https://github.com/bevyengine/bevy/discussions/11496
-        exit.send(AppExit);
-        // Might need to use process exit from the API as the nuclear option.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The positions of the vertices of each column, from the oldest column.
+    fn columns(mountain: &Mountain, mesh: &Mesh) -> Vec<[[f32; 3]; 2]> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("Mountain meshes have positions");
+        };
+        (mountain.first_column..=mountain.newest_column())
+            .map(|column| {
+                let slot = mountain.slot(column);
+                [positions[2 * slot], positions[2 * slot + 1]]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn smooth_mountains_scroll_the_same_after_2_pow_24_columns() {
+        let scene = generate_scene("0x5", GeneratorVersion::V2, &PaletteSet::default());
+        let layer = scene.layers.last().unwrap().clone();
+        let style = PathStyle::Smooth { tolerance: 1.0 };
+        let resolution = WindowResolution::new(500.0, 500.0);
+
+        // The same mountain, just started far enough along that the columns aren't
+        // all distinct as f32s.
+        let mut near = Mountain::from_layer(layer.clone());
+        let mut far = Mountain::from_layer(layer);
+        far.first_column = 1 << 24;
+        far.origin = far.first_column;
+        let (mut near_rng, mut far_rng) = (Randomness::resume(&scene), Randomness::resume(&scene));
+        let mut near_mesh = near.build_mesh(&resolution, style);
+        let mut far_mesh = far.build_mesh(&resolution, style);
+
+        for _ in 0..20 {
+            let scrolled = near.scroll(0.5, &mut near_rng);
+            assert_eq!(far.scroll(0.5, &mut far_rng), scrolled);
+            near.update_mesh(&mut near_mesh, scrolled, &resolution, style);
+            far.update_mesh(&mut far_mesh, scrolled, &resolution, style);
+
+            let far_columns = columns(&far, &far_mesh);
+            assert!(far_columns
+                .iter()
+                .flatten()
+                .flatten()
+                .all(|p| p.is_finite()));
+            assert_eq!(far_columns, columns(&near, &near_mesh));
+        }
     }
 }
-*/
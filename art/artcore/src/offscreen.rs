@@ -0,0 +1,37 @@
+//! Drawing the art to an image instead of a window. Without `WinitPlugin` the primary
+//! window is never opened, it only says what size to draw at, so the app needs no
+//! display. The API reads the frames back from the image.
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    },
+};
+
+/// The image the camera draws to when the art is drawn offscreen.
+#[derive(Clone, Resource, ExtractResource)]
+pub struct OffscreenTarget(pub Handle<Image>);
+
+impl OffscreenTarget {
+    /// An image of the given size to draw to, which we can copy frames out of.
+    pub fn new(images: &mut Assets<Image>, width: u32, height: u32) -> Self {
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0; 4],
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC
+            | TextureUsages::COPY_DST
+            | TextureUsages::RENDER_ATTACHMENT;
+        Self(images.add(image))
+    }
+}
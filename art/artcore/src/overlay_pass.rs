@@ -0,0 +1,340 @@
+//! Draw the overlays over the live view, so the window shows what the published image
+//! will. Sprites can only do normal blending, so this is a post-processing pass after
+//! tonemapping instead, drawing one overlay at a time over what is there so far. The
+//! shader blends the same way as `composite_pixel`, so it matches the CPU compositor.
+
+use super::{BlendMode, OverlayError, OverlayRect, OverlaySources};
+use bevy::{
+    asset::load_internal_asset,
+    core_pipeline::{
+        core_2d::{self, CORE_2D},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        texture::{BevyDefault, CompressedImageFormats, ImageSampler, ImageType},
+        view::{ExtractedView, ViewTarget},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+const OVERLAY_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(178293558428471728424970392085953643303);
+
+/// The name of the pass in the render graph.
+const OVERLAY_PASS: &str = "overlays";
+
+/// The overlays to draw over the live view.
+#[derive(Clone, Debug, Default, ExtractResource, Resource)]
+pub struct LiveOverlays {
+    pub overlays: Vec<LiveOverlay>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveOverlay {
+    pub image: Handle<Image>,
+    pub rect: OverlayRect,
+    pub blend: BlendMode,
+    pub opacity: f32,
+}
+
+impl LiveOverlays {
+    /// Decode the images of the overlays and add them to the app's images.
+    pub fn new(sources: &OverlaySources, images: &mut Assets<Image>) -> Result<Self, OverlayError> {
+        let overlays = sources
+            .overlays
+            .iter()
+            .map(|source| {
+                // Not sRGB, so the shader gets the values in the file, like the CPU
+                // compositor does.
+                let image = Image::from_buffer(
+                    &source.png_data,
+                    ImageType::Extension("png"),
+                    CompressedImageFormats::NONE,
+                    false,
+                    ImageSampler::Default,
+                )
+                .map_err(|err| OverlayError::Image(source.path.clone(), err.into()))?;
+                let size = image.texture_descriptor.size;
+                Ok(LiveOverlay {
+                    rect: source.spec.rect(size.width, size.height),
+                    blend: source.spec.blend,
+                    opacity: source.spec.opacity,
+                    image: images.add(image),
+                })
+            })
+            .collect::<Result<_, OverlayError>>()?;
+        Ok(Self { overlays })
+    }
+}
+
+/// Draws the `LiveOverlays` resource, if there is one, over every 2D camera.
+pub struct OverlayPassPlugin;
+
+impl Plugin for OverlayPassPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            OVERLAY_SHADER_HANDLE,
+            "overlay_pass.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins(ExtractResourcePlugin::<LiveOverlays>::default());
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<OverlayPipeline>>()
+            .add_systems(Render, prepare_overlay_pipelines.in_set(RenderSet::Prepare))
+            .add_render_graph_node::<ViewNodeRunner<OverlayNode>>(CORE_2D, OVERLAY_PASS)
+            .add_render_graph_edges(CORE_2D, &[
+                core_2d::graph::node::CONTRAST_ADAPTIVE_SHARPENING,
+                OVERLAY_PASS,
+                core_2d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+            ]);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<OverlayPipeline>();
+    }
+}
+
+/// The size of `OverlayParams` in `overlay_pass.wgsl`.
+const OVERLAY_PARAMS_SIZE: u64 = 32;
+
+/// The uniforms for drawing an overlay, laid out like `OverlayParams` in
+/// `overlay_pass.wgsl`.
+fn overlay_params(overlay: &LiveOverlay, aspect: f32) -> Vec<u8> {
+    let rect = &overlay.rect;
+    [
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height,
+        aspect,
+        overlay.opacity,
+    ]
+    .into_iter()
+    .flat_map(f32::to_le_bytes)
+    .chain(blend_mode_index(overlay.blend).to_le_bytes())
+    .chain(0u32.to_le_bytes())
+    .collect()
+}
+
+/// The index of the blend mode in the shader.
+fn blend_mode_index(mode: BlendMode) -> u32 {
+    match mode {
+        BlendMode::Normal => 0,
+        BlendMode::Multiply => 1,
+        BlendMode::Screen => 2,
+        BlendMode::Overlay => 3,
+        BlendMode::SoftLight => 4,
+        BlendMode::ColorDodge => 5,
+    }
+}
+
+#[derive(Resource)]
+struct OverlayPipeline {
+    layout: BindGroupLayout,
+    /// The view is read at the same size it is written, so this doesn't filter.
+    screen_sampler: Sampler,
+    overlay_sampler: Sampler,
+}
+
+impl FromWorld for OverlayPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let texture = |binding: u32| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding: u32| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+        let layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("overlay_bind_group_layout"),
+            entries: &[
+                texture(0),
+                sampler(1),
+                texture(2),
+                sampler(3),
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(OVERLAY_PARAMS_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let screen_sampler = render_device.create_sampler(&SamplerDescriptor::default());
+        let overlay_sampler = render_device.create_sampler(&SamplerDescriptor {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        });
+
+        Self {
+            layout,
+            screen_sampler,
+            overlay_sampler,
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for OverlayPipeline {
+    type Key = TextureFormat;
+
+    fn specialize(&self, format: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("overlay_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: OVERLAY_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+#[derive(Component)]
+struct ViewOverlayPipeline(CachedRenderPipelineId);
+
+fn prepare_overlay_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<OverlayPipeline>>,
+    overlay_pipeline: Res<OverlayPipeline>,
+    overlays: Option<Res<LiveOverlays>>,
+    views: Query<(Entity, &ExtractedView)>,
+) {
+    if overlays.is_none() {
+        return;
+    }
+    for (entity, view) in &views {
+        let format = if view.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+        let pipeline_id = pipelines.specialize(&pipeline_cache, &overlay_pipeline, format);
+        commands
+            .entity(entity)
+            .insert(ViewOverlayPipeline(pipeline_id));
+    }
+}
+
+#[derive(Default)]
+struct OverlayNode;
+
+impl ViewNode for OverlayNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ExtractedView,
+        &'static ViewOverlayPipeline,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (target, view, pipeline): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(overlays) = world.get_resource::<LiveOverlays>() else {
+            return Ok(());
+        };
+        // The pipeline takes a few frames to compile.
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline.0)
+        else {
+            return Ok(());
+        };
+        let overlay_pipeline = world.resource::<OverlayPipeline>();
+        let images = world.resource::<RenderAssets<Image>>();
+        let aspect = view.viewport.w as f32 / view.viewport.z as f32;
+
+        for overlay in &overlays.overlays {
+            let Some(image) = images.get(&overlay.image) else {
+                continue;
+            };
+
+            let params =
+                render_context
+                    .render_device()
+                    .create_buffer_with_data(&BufferInitDescriptor {
+                        label: Some("overlay_params"),
+                        contents: &overlay_params(overlay, aspect),
+                        usage: BufferUsages::UNIFORM,
+                    });
+
+            // Each overlay is drawn over the result of the last one.
+            let post_process = target.post_process_write();
+            let bind_group = render_context.render_device().create_bind_group(
+                Some("overlay_bind_group"),
+                &overlay_pipeline.layout,
+                &BindGroupEntries::sequential((
+                    post_process.source,
+                    &overlay_pipeline.screen_sampler,
+                    &image.texture_view,
+                    &overlay_pipeline.overlay_sampler,
+                    params.as_entire_binding(),
+                )),
+            );
+
+            let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("overlay_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.destination,
+                    resolve_target: None,
+                    ops: Operations::default(),
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_render_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        Ok(())
+    }
+}
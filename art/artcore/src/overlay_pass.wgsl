@@ -0,0 +1,117 @@
+// Draw an overlay over the view, blending it like `composite_pixel` in blend.rs does.
+// That works on the 8 bit sRGB values of the image, whereas the view holds linear
+// colors, so we convert the view to sRGB to blend and back again after.
+
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct OverlayParams {
+    // The x, y, width and height of the overlay, as fractions of the view's width.
+    rect: vec4<f32>,
+    // The view's height over its width.
+    aspect: f32,
+    opacity: f32,
+    // Which blend mode, see `blend_mode_index`.
+    mode: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var screen_sampler: sampler;
+@group(0) @binding(2) var overlay_texture: texture_2d<f32>;
+@group(0) @binding(3) var overlay_sampler: sampler;
+@group(0) @binding(4) var<uniform> params: OverlayParams;
+
+fn to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3(0.0031308));
+}
+
+fn to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3(2.4));
+    return select(high, low, srgb <= vec3(0.04045));
+}
+
+fn screen(cb: f32, cs: f32) -> f32 {
+    return cb + cs - cb * cs;
+}
+
+fn blend_channel(cb: f32, cs: f32) -> f32 {
+    switch params.mode {
+        // Multiply.
+        case 1u: {
+            return cb * cs;
+        }
+        // Screen.
+        case 2u: {
+            return screen(cb, cs);
+        }
+        // Overlay.
+        case 3u: {
+            if cb <= 0.5 {
+                return cs * 2.0 * cb;
+            }
+            return screen(cs, 2.0 * cb - 1.0);
+        }
+        // Soft light.
+        case 4u: {
+            if cs <= 0.5 {
+                return cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb);
+            }
+            var d = sqrt(cb);
+            if cb <= 0.25 {
+                d = ((16.0 * cb - 12.0) * cb + 4.0) * cb;
+            }
+            return cb + (2.0 * cs - 1.0) * (d - cb);
+        }
+        // Color dodge.
+        case 5u: {
+            if cb == 0.0 {
+                return 0.0;
+            }
+            if cs >= 1.0 {
+                return 1.0;
+            }
+            return min(cb / (1.0 - cs), 1.0);
+        }
+        // Normal.
+        default: {
+            return cs;
+        }
+    }
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let backdrop = textureSample(screen_texture, screen_sampler, in.uv);
+
+    // Where we are, in fractions of the view's width like the rect.
+    let position = vec2(in.uv.x, in.uv.y * params.aspect);
+    let overlay_uv = (position - params.rect.xy) / params.rect.zw;
+    // Sample before we branch, textureSample has to be in uniform control flow.
+    let source = textureSample(overlay_texture, overlay_sampler, clamp(overlay_uv, vec2(0.0), vec2(1.0)));
+    if any(overlay_uv < vec2(0.0)) || any(overlay_uv > vec2(1.0)) {
+        return backdrop;
+    }
+
+    let cb = to_srgb(backdrop.rgb);
+    let cs = source.rgb;
+    let alpha_b = backdrop.a;
+    let alpha_s = source.a * params.opacity;
+
+    // Source-over, in premultiplied terms: co = cs + cb * (1 - αs).
+    let alpha_o = alpha_s + alpha_b * (1.0 - alpha_s);
+    if alpha_o == 0.0 {
+        return vec4(0.0);
+    }
+
+    let blended = vec3(
+        blend_channel(cb.r, cs.r),
+        blend_channel(cb.g, cs.g),
+        blend_channel(cb.b, cs.b),
+    );
+    let mixed = (1.0 - alpha_b) * cs + alpha_b * blended;
+    let premultiplied = alpha_s * mixed + alpha_b * cb * (1.0 - alpha_s);
+    return vec4(to_linear(premultiplied / alpha_o), alpha_o);
+}
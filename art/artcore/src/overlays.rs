@@ -0,0 +1,562 @@
+//! The images drawn over the art before it is published, like the paper texture and
+//! the summit lockup. These are declared in a YAML file rather than built in to the
+//! binary, see `assets/overlays.yaml` for an example. Each overlay is placed relative
+//! to the size of the art, so the same overlays work at any output width; they are
+//! scaled to fit when the art is rendered at a different size to the overlay image.
+//!
+//! `Overlays` draws them on the CPU, over the images we publish. The live view draws
+//! the same `OverlaySources` on the GPU instead, see `overlay_pass`.
+
+use super::BlendMode;
+use serde::Deserialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "raster")]
+use {
+    super::{composite_source, SourcePixel},
+    image::{imageops::FilterType, RgbaImage},
+    rayon::prelude::*,
+    std::sync::{Arc, Mutex},
+};
+
+/// How many widths of art we keep the overlays placed and scaled for. Scaling the
+/// overlays is slow and we almost always render at the one width, but anyone using
+/// the API can ask for others, so we only keep the ones we used last.
+#[cfg(feature = "raster")]
+const PLACED_WIDTHS: usize = 4;
+
+/// Where we look for the overlays if no file is given, relative to the working
+/// directory. This is the one for the 2024 summit.
+pub const DEFAULT_OVERLAYS: &str = "assets/overlays.yaml";
+
+/// The overlays as they are declared in YAML.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OverlaySet {
+    /// Drawn in order, so the last one ends up on top.
+    pub overlays: Vec<OverlaySpec>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OverlaySpec {
+    /// The PNG to draw, relative to the file the overlays are declared in.
+    pub path: PathBuf,
+
+    /// Which point of the overlay lines up with the same point of the art.
+    #[serde(default)]
+    pub anchor: Anchor,
+
+    /// How far to move the overlay from where the anchor puts it, as a fraction of
+    /// the width of the art. Positive x is to the right and positive y is down.
+    #[serde(default)]
+    pub position: [f32; 2],
+
+    #[serde(default)]
+    pub scale: OverlayScale,
+
+    /// How to blend the overlay with the art under it.
+    #[serde(default)]
+    pub blend: BlendMode,
+
+    /// How opaque to draw the overlay, from 0 to 1. This is on top of the overlay's
+    /// own alpha.
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// How far across and down the overlay and the art the anchor is, from 0 to 1.
+    fn fractions(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// How big to draw an overlay. In YAML this is either `fill`, or a number.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(try_from = "RawOverlayScale")]
+pub enum OverlayScale {
+    /// Stretch the overlay over the whole art.
+    #[default]
+    Fill,
+
+    /// Make the overlay this fraction of the width of the art, with its height
+    /// keeping its aspect ratio.
+    Width(f32),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawOverlayScale {
+    Named(String),
+    Width(f32),
+}
+
+impl TryFrom<RawOverlayScale> for OverlayScale {
+    type Error = String;
+
+    fn try_from(raw: RawOverlayScale) -> Result<Self, Self::Error> {
+        match raw {
+            RawOverlayScale::Named(name) if name == "fill" => Ok(OverlayScale::Fill),
+            RawOverlayScale::Named(name) => Err(format!(
+                "Invalid scale {}, expected fill or a fraction of the width",
+                name
+            )),
+            RawOverlayScale::Width(width) => Ok(OverlayScale::Width(width)),
+        }
+    }
+}
+
+/// Where an overlay is drawn, as fractions of the width of the art.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl OverlaySpec {
+    /// Where to draw the overlay, given the size of its image.
+    pub fn rect(&self, image_width: u32, image_height: u32) -> OverlayRect {
+        let (width, height) = match self.scale {
+            OverlayScale::Fill => (1.0, 1.0),
+            OverlayScale::Width(width) => (width, width * image_height as f32 / image_width as f32),
+        };
+        let (across, down) = self.anchor.fractions();
+        OverlayRect {
+            x: across * (1.0 - width) + self.position[0],
+            y: down * (1.0 - height) + self.position[1],
+            width,
+            height,
+        }
+    }
+}
+
+impl OverlaySet {
+    /// Parse the overlays declared in YAML. The path is where the YAML came from, for
+    /// errors. This doesn't load their images.
+    pub fn from_yaml(yaml: &str, path: &Path) -> Result<Self, OverlayError> {
+        let set: OverlaySet =
+            serde_yaml::from_str(yaml).map_err(|err| OverlayError::Parse(path.into(), err))?;
+        set.validate()?;
+        Ok(set)
+    }
+
+    fn validate(&self) -> Result<(), OverlayError> {
+        for overlay in &self.overlays {
+            let invalid = |reason: &str| {
+                Err(OverlayError::Invalid(format!(
+                    "Overlay {} {}",
+                    overlay.path.display(),
+                    reason
+                )))
+            };
+            if let OverlayScale::Width(width) = overlay.scale {
+                if !(width.is_finite() && width > 0.0) {
+                    return invalid("must have a positive width");
+                }
+            }
+            if !(0.0..=1.0).contains(&overlay.opacity) {
+                return invalid("must have an opacity between 0 and 1");
+            }
+            if !overlay.position.iter().all(|p| p.is_finite()) {
+                return invalid("has an invalid position");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The overlays as they are declared, with the PNG data of their images. This is
+/// what the live view needs, the rest is done on the GPU.
+#[derive(Clone, Debug, Default)]
+pub struct OverlaySources {
+    pub overlays: Vec<OverlaySource>,
+}
+
+#[derive(Clone)]
+pub struct OverlaySource {
+    pub spec: OverlaySpec,
+
+    /// Where the image was read from.
+    pub path: PathBuf,
+
+    pub png_data: Vec<u8>,
+}
+
+impl fmt::Debug for OverlaySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OverlaySource")
+            .field("spec", &self.spec)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OverlaySources {
+    /// Parse the overlays declared in YAML and read their images with `read`. The
+    /// path is where the YAML came from, the paths of the images are relative to it.
+    /// This is for where there is no filesystem, like the browser, otherwise use
+    /// `load`.
+    pub fn from_yaml(
+        yaml: &str,
+        path: &Path,
+        read: impl Fn(&Path) -> std::io::Result<Vec<u8>>,
+    ) -> Result<Self, OverlayError> {
+        let set = OverlaySet::from_yaml(yaml, path)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let overlays = set
+            .overlays
+            .into_iter()
+            .map(|spec| {
+                let path = dir.join(&spec.path);
+                let png_data = read(&path).map_err(|err| OverlayError::Io(path.clone(), err))?;
+                Ok(OverlaySource {
+                    spec,
+                    path,
+                    png_data,
+                })
+            })
+            .collect::<Result<_, OverlayError>>()?;
+        Ok(Self { overlays })
+    }
+
+    /// Load the overlays declared in the YAML file at the path, and their images.
+    pub fn load(path: &Path) -> Result<Self, OverlayError> {
+        let yaml =
+            std::fs::read_to_string(path).map_err(|err| OverlayError::Io(path.into(), err))?;
+        Self::from_yaml(&yaml, path, |path| std::fs::read(path))
+    }
+
+    /// Load the overlays at the path, or the ones at `DEFAULT_OVERLAYS` if there
+    /// isn't one.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, OverlayError> {
+        Self::load(path.unwrap_or(Path::new(DEFAULT_OVERLAYS)))
+    }
+}
+
+/// The overlays with their images decoded, ready to be drawn over the art.
+#[cfg(feature = "raster")]
+pub struct Overlays {
+    overlays: Vec<Overlay>,
+
+    /// The overlays placed and scaled for the last few widths of art we've drawn them
+    /// over, the most recently used last.
+    placed: Mutex<Vec<(u32, Arc<Vec<PlacedOverlay>>)>>,
+}
+
+#[cfg(feature = "raster")]
+struct Overlay {
+    spec: OverlaySpec,
+    png_data: Vec<u8>,
+    image: RgbaImage,
+}
+
+/// An overlay scaled and placed for art of a particular width.
+#[cfg(feature = "raster")]
+struct PlacedOverlay {
+    /// The scaled overlay's pixels, row by row, with the opacity applied.
+    pixels: Vec<SourcePixel>,
+    width: u32,
+    height: u32,
+    x: i64,
+    y: i64,
+    blend: BlendMode,
+}
+
+#[cfg(feature = "raster")]
+impl fmt::Debug for Overlays {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.overlays.iter().map(|overlay| &overlay.spec))
+            .finish()
+    }
+}
+
+#[cfg(feature = "raster")]
+impl Overlays {
+    /// No overlays at all, the art as it is generated.
+    pub fn none() -> Self {
+        Self::from_overlays(Vec::new())
+    }
+
+    /// Load the overlays declared in the YAML file at the path, and their images.
+    pub fn load(path: &Path) -> Result<Self, OverlayError> {
+        Self::decode(OverlaySources::load(path)?)
+    }
+
+    /// Load the overlays at the path, or the ones at `DEFAULT_OVERLAYS` if there
+    /// isn't one.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, OverlayError> {
+        Self::decode(OverlaySources::load_or_default(path)?)
+    }
+
+    /// Decode the images of the overlays.
+    pub fn decode(sources: OverlaySources) -> Result<Self, OverlayError> {
+        let overlays = sources
+            .overlays
+            .into_iter()
+            .map(|source| {
+                let image = image::load_from_memory(&source.png_data)
+                    .map_err(|err| OverlayError::Image(source.path, err.into()))?
+                    .to_rgba8();
+                Ok(Overlay {
+                    spec: source.spec,
+                    png_data: source.png_data,
+                    image,
+                })
+            })
+            .collect::<Result<_, OverlayError>>()?;
+
+        Ok(Self::from_overlays(overlays))
+    }
+
+    fn from_overlays(overlays: Vec<Overlay>) -> Self {
+        Self {
+            overlays,
+            placed: Mutex::new(Vec::with_capacity(PLACED_WIDTHS)),
+        }
+    }
+
+    /// Draw the overlays over the art. Overlays that hang off the edge of the art are
+    /// cropped. We do this in one pass over the art, drawing every overlay over a row
+    /// before moving on to the next, and the rows in parallel.
+    pub fn apply(&self, image: &mut RgbaImage) {
+        if self.overlays.is_empty() {
+            return;
+        }
+        let placed = self.placed(image.width());
+        let row_len = image.width() as usize * 4;
+        image
+            .par_chunks_exact_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for overlay in placed.iter() {
+                    overlay.composite_row(row, y as i64);
+                }
+            });
+    }
+
+    /// The overlays for the SVG export, which scales and places them itself.
+    #[cfg(feature = "svg")]
+    pub fn svg_overlays(&self) -> Vec<super::SvgOverlay<'_>> {
+        self.overlays
+            .iter()
+            .map(|overlay| super::SvgOverlay {
+                png_data: &overlay.png_data,
+                rect: overlay
+                    .spec
+                    .rect(overlay.image.width(), overlay.image.height()),
+                blend: overlay.spec.blend,
+                opacity: overlay.spec.opacity,
+            })
+            .collect()
+    }
+
+    fn placed(&self, width: u32) -> Arc<Vec<PlacedOverlay>> {
+        let mut placed = self.placed.lock().unwrap();
+        let entry = match placed
+            .iter()
+            .position(|(placed_width, _)| *placed_width == width)
+        {
+            Some(i) => placed.remove(i),
+            None => {
+                if placed.len() == PLACED_WIDTHS {
+                    placed.remove(0);
+                }
+                let overlays = self.overlays.iter().map(|o| o.place(width)).collect();
+                (width, Arc::new(overlays))
+            },
+        };
+        let overlays = entry.1.clone();
+        placed.push(entry);
+        overlays
+    }
+}
+
+#[cfg(feature = "raster")]
+impl Overlay {
+    /// Scale the overlay for art of the given width. If it is already the right size,
+    /// like the overlays drawn for the size we publish at, it is used as it is.
+    fn place(&self, width: u32) -> PlacedOverlay {
+        let rect = self.spec.rect(self.image.width(), self.image.height());
+        let pixels = |fraction: f32| (fraction * width as f32).round();
+        let (scaled_width, scaled_height) = (
+            pixels(rect.width).max(1.0) as u32,
+            pixels(rect.height).max(1.0) as u32,
+        );
+        let resized;
+        let image = if self.image.dimensions() == (scaled_width, scaled_height) {
+            &self.image
+        } else {
+            resized = image::imageops::resize(
+                &self.image,
+                scaled_width,
+                scaled_height,
+                FilterType::Lanczos3,
+            );
+            &resized
+        };
+        PlacedOverlay {
+            pixels: image
+                .pixels()
+                .map(|pixel| SourcePixel::new(pixel.0, self.spec.opacity))
+                .collect(),
+            width: image.width(),
+            height: image.height(),
+            x: pixels(rect.x) as i64,
+            y: pixels(rect.y) as i64,
+            blend: self.spec.blend,
+        }
+    }
+}
+
+#[cfg(feature = "raster")]
+impl PlacedOverlay {
+    /// Blend the overlay in to the part of the row of the art it covers.
+    fn composite_row(&self, row: &mut [u8], y: i64) {
+        let source_y = y - self.y;
+        if !(0..self.height as i64).contains(&source_y) {
+            return;
+        }
+        let row_width = (row.len() / 4) as i64;
+        let left = self.x.clamp(0, row_width);
+        let right = (self.x + self.width as i64).clamp(0, row_width);
+        if left >= right {
+            return;
+        }
+
+        let start = (source_y * self.width as i64 + left - self.x) as usize;
+        let sources = &self.pixels[start..start + (right - left) as usize];
+        let pixels = row[left as usize * 4..right as usize * 4].chunks_exact_mut(4);
+        for (pixel, source) in pixels.zip(sources) {
+            let backdrop = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            pixel.copy_from_slice(&composite_source(backdrop, *source, self.blend));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OverlayError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_yaml::Error),
+    Image(PathBuf, Box<dyn std::error::Error + Send + Sync>),
+    Invalid(String),
+}
+
+impl fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlayError::Io(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            OverlayError::Parse(path, err) => {
+                write!(f, "Failed to parse overlays {}: {}", path.display(), err)
+            },
+            OverlayError::Image(path, err) => {
+                write!(f, "Failed to decode overlay {}: {}", path.display(), err)
+            },
+            OverlayError::Invalid(reason) => write!(f, "Invalid overlays: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for OverlayError {}
+
+#[cfg(all(test, feature = "raster"))]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn overlays_are_placed_and_cropped() {
+        let red = Rgba([255, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        // Half the width of the art, in the bottom right corner and moved a quarter
+        // of the width right, so it hangs off the edge.
+        let overlays = Overlays::from_overlays(vec![Overlay {
+            spec: OverlaySpec {
+                path: PathBuf::from("red.png"),
+                anchor: Anchor::BottomRight,
+                position: [0.25, 0.0],
+                scale: OverlayScale::Width(0.5),
+                blend: BlendMode::Normal,
+                opacity: 1.0,
+            },
+            png_data: Vec::new(),
+            image: RgbaImage::from_pixel(2, 2, red),
+        }]);
+
+        let mut art = RgbaImage::from_pixel(4, 4, white);
+        overlays.apply(&mut art);
+
+        for (x, y, pixel) in art.enumerate_pixels() {
+            let expected = if x == 3 && y >= 2 { red } else { white };
+            assert_eq!(*pixel, expected, "Pixel {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn only_the_last_few_widths_are_kept_placed() {
+        let overlays = Overlays::from_overlays(vec![Overlay {
+            spec: OverlaySpec {
+                path: PathBuf::from("red.png"),
+                anchor: Anchor::TopLeft,
+                position: [0.0, 0.0],
+                scale: OverlayScale::Fill,
+                blend: BlendMode::Normal,
+                opacity: 1.0,
+            },
+            png_data: Vec::new(),
+            image: RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255])),
+        }]);
+
+        let first = overlays.placed(10);
+        for width in 11..20 {
+            overlays.placed(width);
+        }
+        let widths: Vec<u32> = overlays
+            .placed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(widths, (20 - PLACED_WIDTHS as u32..20).collect::<Vec<_>>());
+
+        // Using a width keeps it, so the width we render at isn't scaled again.
+        let again = overlays.placed(16);
+        overlays.placed(20);
+        assert!(Arc::ptr_eq(&again, &overlays.placed(16)));
+        assert!(!Arc::ptr_eq(&first, &overlays.placed(10)));
+    }
+}
@@ -0,0 +1,161 @@
+//! The art as a Bevy plugin, so it can be added to an app of our own like the event
+//! kiosk, not just the ones `AppConfig` builds.
+
+#[cfg(feature = "api")]
+use super::{
+    capture::{CapturePlugin, FrameOverlays},
+    token_address_listener, ApiOptions,
+};
+use super::{
+    ArtGenerator, GeneratorRegistry, GeneratorVersion, LiveOverlays, OffscreenTarget,
+    OverlayPassPlugin, OverlaySources, PaletteSet, PathStyle, DEFAULT_GENERATOR,
+};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_prototype_lyon::prelude::*;
+use std::sync::Arc;
+
+/// Draws the art for a token in the primary window, at the size of the window. Add
+/// this after `DefaultPlugins`, the overlays need the render app. The art spawns its
+/// own camera.
+pub struct SummitsPlugin {
+    /// The generator to draw the art with.
+    pub generator: Arc<dyn ArtGenerator>,
+
+    /// Every generator the app can draw with. Their systems are all added, so
+    /// requests to the API can switch between them.
+    pub generators: GeneratorRegistry,
+
+    pub token_address: String,
+    pub generator_version: GeneratorVersion,
+    pub palettes: PaletteSet,
+    pub path_style: PathStyle,
+
+    /// Whether the art starts paused. P pauses and unpauses it.
+    pub paused: bool,
+
+    /// The overlays to draw over the art, if any.
+    pub overlays: Option<OverlaySources>,
+
+    /// Draw to an `OffscreenTarget` the size of the primary window instead of to the
+    /// window. Disable `WinitPlugin` too and the window is never opened.
+    pub offscreen: bool,
+
+    /// Render the art the API asks for and send back images of it.
+    #[cfg(feature = "api")]
+    pub api: Option<ApiOptions>,
+}
+
+impl SummitsPlugin {
+    /// Draw the token's art with the default generator and palettes, without
+    /// overlays. Change the rest with struct update syntax.
+    pub fn new(token_address: impl Into<String>, generator_version: GeneratorVersion) -> Self {
+        let generators = GeneratorRegistry::default();
+        let generator = generators
+            .find(DEFAULT_GENERATOR)
+            .expect("The default generator is built in");
+        Self {
+            generator,
+            generators,
+            token_address: token_address.into(),
+            generator_version,
+            palettes: PaletteSet::default(),
+            path_style: PathStyle::default(),
+            paused: false,
+            overlays: None,
+            offscreen: false,
+            #[cfg(feature = "api")]
+            api: None,
+        }
+    }
+}
+
+impl Plugin for SummitsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AppSeed {
+            token_address: self.token_address.clone(),
+            generator_version: self.generator_version,
+            palettes: self.palettes.clone(),
+            path_style: self.path_style,
+            generator: self.generator.clone(),
+            generators: self.generators.clone(),
+        })
+        .insert_resource(PauseState {
+            paused: self.paused,
+        })
+        .add_systems(Startup, initial_spawn)
+        .add_systems(Update, handle_keys);
+
+        // The app we're added to might draw shapes too.
+        if !app.is_plugin_added::<ShapePlugin>() {
+            app.add_plugins(ShapePlugin);
+        }
+
+        for generator in self.generators.iter() {
+            generator.build(app);
+        }
+
+        if self.offscreen {
+            let resolution = app
+                .world
+                .query_filtered::<&Window, With<PrimaryWindow>>()
+                .single(&app.world)
+                .resolution
+                .clone();
+            let target = OffscreenTarget::new(
+                &mut app.world.resource_mut(),
+                resolution.physical_width(),
+                resolution.physical_height(),
+            );
+            app.insert_resource(target);
+        }
+
+        if let Some(overlays) = &self.overlays {
+            let overlays = LiveOverlays::new(overlays, &mut app.world.resource_mut())
+                .unwrap_or_else(|err| panic!("{}", err));
+            app.insert_resource(overlays).add_plugins(OverlayPassPlugin);
+        }
+
+        #[cfg(feature = "api")]
+        if let Some(api) = &self.api {
+            app.insert_resource(FrameOverlays(api.overlays.clone()))
+                .insert_resource(api.channels.token_address_receiver.clone())
+                .add_plugins(CapturePlugin)
+                .add_systems(Update, token_address_listener);
+        }
+    }
+}
+
+/// What the art is drawn from.
+#[derive(Resource)]
+pub struct AppSeed {
+    pub token_address: String,
+    pub generator_version: GeneratorVersion,
+    pub palettes: PaletteSet,
+    pub path_style: PathStyle,
+    pub generator: Arc<dyn ArtGenerator>,
+    /// Every generator the app can draw with. The app has the systems of all of them,
+    /// so requests to the API can switch between them.
+    pub generators: GeneratorRegistry,
+}
+
+/// Whether the mountains and clouds are scrolling.
+#[derive(Resource)]
+pub struct PauseState {
+    pub paused: bool,
+}
+
+fn initial_spawn(world: &mut World) {
+    let app_seed = world.resource::<AppSeed>();
+    let generator = app_seed.generator.clone();
+    let token_address = app_seed.token_address.clone();
+    let version = app_seed.generator_version;
+    let palettes = app_seed.palettes.clone();
+    generator.spawn(world, &token_address, version, &palettes);
+}
+
+fn handle_keys(keyboard_input: Res<Input<KeyCode>>, mut pause_state: ResMut<PauseState>) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        pause_state.paused = !pause_state.paused;
+        info!("Paused: {}", pause_state.paused);
+    }
+}
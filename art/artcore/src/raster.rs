@@ -0,0 +1,249 @@
+//! A software rasterizer for the mountain scene. This draws the same sky and mountain
+//! layers that the Bevy app spawns, but straight into an `RgbaImage` on the
+//! CPU, so it needs no window, no GPU and no display server (e.g. Xvfb).
+
+use super::{
+    generate_scene, sample_ridge, Circle, FogSpec, Foreground, GeneratorVersion, HazeBand,
+    LayerSpec, PaletteSet, PathStyle, SceneColor, SceneSpec, SkySpec, VerticalGradient, SCENE_SIZE,
+};
+use bevy::math::Vec2;
+use image::{Rgba, RgbaImage};
+
+/// Render the art for the given token address at the given width. The art is square.
+pub fn rasterize_token(
+    token_address: &str,
+    version: GeneratorVersion,
+    palettes: &PaletteSet,
+    width: u32,
+    style: PathStyle,
+) -> RgbaImage {
+    rasterize_scene(
+        &generate_scene(token_address, version, palettes),
+        width,
+        style,
+    )
+}
+
+/// Render the scene at the given width, with the ridgelines drawn in the given style.
+/// The art is square.
+pub fn rasterize_scene(scene: &SceneSpec, width: u32, style: PathStyle) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(width, width, Rgba(scene.palette.sky.as_rgba_u8()));
+
+    fill_sky(&mut image, &scene.sky);
+
+    // The foreground is ordered back to front, so we can just paint it in order.
+    for element in scene.foreground() {
+        match element {
+            Foreground::Mountain(layer) => fill_layer(&mut image, layer, style),
+            Foreground::Haze(band) => fill_haze(&mut image, scene.fog.as_ref().unwrap(), band),
+        }
+    }
+
+    image
+}
+
+/// Fill everything below the ridgeline of the mountain. This mirrors the geometry
+/// built by `Mountain::build_mesh` and the transform applied in `Mountain::spawn`:
+/// the mesh starts at the left edge of the window, has a column of vertices per scene
+/// unit, and is shifted down by a third of the window height. Like the mesh, smooth
+/// ridgelines are sampled at each column and the samples joined with straight lines.
+fn fill_layer(image: &mut RgbaImage, layer: &LayerSpec, style: PathStyle) {
+    let (width, height) = image.dimensions();
+
+    let smoothed;
+    let heights = match style {
+        PathStyle::Polyline => &layer.heights,
+        PathStyle::Smooth { .. } => {
+            let points: Vec<Vec2> = (layer.heights.iter().enumerate())
+                .map(|(i, height)| Vec2::new(i as f32, *height))
+                .collect();
+            let mut samples = Vec::new();
+            sample_ridge(&points, style, &mut samples);
+            smoothed = samples;
+            &smoothed
+        },
+    };
+
+    // Scale from scene units to pixels.
+    let scale = width as f32 / SCENE_SIZE;
+
+    // In pixels from the top of the image, where a world height of 0 ends up.
+    let baseline = height as f32 / 2.0 + height as f32 / 3.0;
+
+    for x in 0..width {
+        // Sample the ridgeline at the center of the pixel.
+        let sample = (x as f32 + 0.5) / scale;
+        let ridge = baseline - height_at(heights, sample) * scale;
+
+        // Everything below the ridge is mountain. The pixel the ridge passes through
+        // is blended based on how much of it is covered, which gives us a cheap bit
+        // of antialiasing along the ridgeline.
+        let first_row = ridge.floor().max(0.0) as u32;
+        for y in first_row..height {
+            let color = layer.color_at((height as f32 - y as f32 - 0.5) / scale);
+            let coverage = (y as f32 + 1.0 - ridge).clamp(0.0, 1.0) * color.a;
+            if coverage <= 0.0 {
+                continue;
+            }
+            blend_pixel(image.get_pixel_mut(x, y), color, coverage);
+        }
+    }
+}
+
+/// Draw the stars, the sun or moon and the clouds, in the same order as the z values
+/// they get in the Bevy app. The clouds are drawn where they start, before drifting.
+fn fill_sky(image: &mut RgbaImage, sky: &SkySpec) {
+    if let Some(gradient) = &sky.gradient {
+        fill_gradient(image, gradient);
+    }
+
+    if let Some(star_field) = &sky.star_field {
+        for star in &star_field.stars {
+            fill_circle(image, star, star_field.color);
+        }
+    }
+
+    if let Some(body) = &sky.celestial_body {
+        fill_circle(image, &body.circle, body.color);
+    }
+
+    for layer in &sky.cloud_layers {
+        for cloud in &layer.clouds {
+            for puff in &cloud.puffs {
+                let circle = Circle {
+                    x: cloud.x + puff.x,
+                    y: cloud.y + puff.y,
+                    radius: puff.radius,
+                };
+                fill_circle(image, &circle, layer.color);
+            }
+        }
+    }
+}
+
+/// Fill a circle given in scene units from the top left of the scene. Pixels on the
+/// edge are blended based on roughly how much of them is inside the circle.
+fn fill_circle(image: &mut RgbaImage, circle: &Circle, color: SceneColor) {
+    let (width, height) = image.dimensions();
+
+    // Scale from scene units to pixels.
+    let scale = width as f32 / SCENE_SIZE;
+    let (cx, cy, radius) = (circle.x * scale, circle.y * scale, circle.radius * scale);
+
+    let min_x = (cx - radius).floor().max(0.0) as u32;
+    let max_x = ((cx + radius).ceil().max(0.0) as u32).min(width);
+    let min_y = (cy - radius).floor().max(0.0) as u32;
+    let max_y = ((cy + radius).ceil().max(0.0) as u32).min(height);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy);
+            let coverage = (radius - distance + 0.5).clamp(0.0, 1.0) * color.a;
+            if coverage <= 0.0 {
+                continue;
+            }
+            blend_pixel(image.get_pixel_mut(x, y), color, coverage);
+        }
+    }
+}
+
+/// Blend the gradient over the whole image, a row at a time.
+fn fill_gradient(image: &mut RgbaImage, gradient: &VerticalGradient) {
+    let (width, height) = image.dimensions();
+
+    // Scale from scene units to pixels.
+    let scale = width as f32 / SCENE_SIZE;
+
+    for y in 0..height {
+        let altitude = (height as f32 - y as f32 - 0.5) / scale;
+        let color = gradient.color_at(altitude);
+        for x in 0..width {
+            blend_pixel(image.get_pixel_mut(x, y), color, color.a);
+        }
+    }
+}
+
+/// Blend the fog color over the whole image, more of it the lower down we are.
+fn fill_haze(image: &mut RgbaImage, fog: &FogSpec, band: &HazeBand) {
+    let (width, height) = image.dimensions();
+
+    // Scale from scene units to pixels.
+    let scale = width as f32 / SCENE_SIZE;
+
+    for y in 0..height {
+        let altitude = (height as f32 - y as f32 - 0.5) / scale;
+        let coverage = fog.haze_alpha(band, altitude) * fog.color.a;
+        for x in 0..width {
+            blend_pixel(image.get_pixel_mut(x, y), fog.color, coverage);
+        }
+    }
+}
+
+/// Linearly interpolate between the two height samples either side of `sample`.
+pub(crate) fn height_at(heights: &[f32], sample: f32) -> f32 {
+    let last = heights.len() - 1;
+    let index = (sample.floor() as usize).min(last);
+    let next = (index + 1).min(last);
+    let fraction = sample - sample.floor();
+    heights[index] * (1.0 - fraction) + heights[next] * fraction
+}
+
+fn blend_pixel(pixel: &mut Rgba<u8>, color: SceneColor, coverage: f32) {
+    for (channel, value) in pixel.0.iter_mut().zip([color.r, color.g, color.b]) {
+        let base = *channel as f32 / 255.0;
+        *channel = to_u8(base * (1.0 - coverage) + value * coverage);
+    }
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_are_filled_below_the_ridgeline() {
+        let mut layer = generate_scene("0x5", GeneratorVersion::V1, &PaletteSet::default())
+            .layers
+            .remove(0);
+        // A flat ridge a quarter of the scene up from the baseline, which is a sixth of
+        // the way up the scene.
+        layer.heights = vec![SCENE_SIZE / 4.0; layer.heights.len()];
+        layer.color = SceneColor::rgb_u8(0, 0, 0);
+        layer.shade = None;
+
+        let white = Rgba([255, 255, 255, 255]);
+        let mut image = RgbaImage::from_pixel(120, 120, white);
+        fill_layer(&mut image, &layer, PathStyle::Polyline);
+
+        // The ridge is at 120 * (1 - 1/6 - 1/4) = 70 pixels down.
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let expected = if y < 70 { white } else { Rgba([0, 0, 0, 255]) };
+            assert_eq!(*pixel, expected, "Pixel {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn smooth_ridgelines_are_filled_through_their_samples() {
+        let mut layer = generate_scene("0x5", GeneratorVersion::V1, &PaletteSet::default())
+            .layers
+            .remove(0);
+        let style = PathStyle::Smooth { tolerance: 5.0 };
+        let white = Rgba([255, 255, 255, 255]);
+        let mut smooth = RgbaImage::from_pixel(120, 120, white);
+        fill_layer(&mut smooth, &layer, style);
+
+        let points: Vec<Vec2> = (layer.heights.iter().enumerate())
+            .map(|(i, height)| Vec2::new(i as f32, *height))
+            .collect();
+        let mut samples = Vec::new();
+        sample_ridge(&points, style, &mut samples);
+        layer.heights = samples;
+        let mut sampled = RgbaImage::from_pixel(120, 120, white);
+        fill_layer(&mut sampled, &layer, PathStyle::Polyline);
+
+        assert!(smooth == sampled);
+    }
+}
@@ -0,0 +1,103 @@
+//! Render a token's art straight to an image. This draws with the software rasterizer,
+//! so unlike going through the Bevy app there is no app, window or channels to set up,
+//! and it can be called as often as we like from tools and tests.
+
+use super::{
+    ConfigError, GeneratorRegistry, GeneratorVersion, Overlays, PaletteSet, PathStyle,
+    UnknownGenerator, DEFAULT_GENERATOR,
+};
+use image::{ImageError, ImageOutputFormat, RgbaImage};
+use std::{fmt, io::Cursor, sync::Arc};
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// The name of the generator to draw the art with.
+    pub generator: String,
+
+    pub version: GeneratorVersion,
+
+    /// The width (and height) of the image. Defaults to the width the generator's art
+    /// is published at.
+    pub width: Option<u32>,
+
+    pub palettes: Arc<PaletteSet>,
+
+    /// Drawn over the art. These are loaded from files, so by default there are none,
+    /// use `Overlays::load_or_default` for the published ones.
+    pub overlays: Arc<Overlays>,
+
+    /// How to draw the ridgelines, like `AppConfig::smooth_paths` does for the app.
+    pub path_style: PathStyle,
+}
+
+impl Default for RenderOptions {
+    /// The default generator, at v1 like the 2024 summit.
+    fn default() -> Self {
+        Self {
+            generator: DEFAULT_GENERATOR.to_string(),
+            version: GeneratorVersion::V1,
+            width: None,
+            palettes: Arc::new(PaletteSet::default()),
+            overlays: Arc::new(Overlays::none()),
+            path_style: PathStyle::default(),
+        }
+    }
+}
+
+/// Render the art for the given token address, with the overlays.
+pub fn render_image(
+    token_address: &str,
+    options: &RenderOptions,
+) -> Result<RgbaImage, RenderError> {
+    let generator = GeneratorRegistry::default()
+        .find(&options.generator)
+        .map_err(RenderError::UnknownGenerator)?;
+    let width = options.width.unwrap_or_else(|| generator.default_width());
+    let mut image = generator.rasterize(
+        token_address,
+        options.version,
+        &options.palettes,
+        width,
+        options.path_style,
+    );
+    options.overlays.apply(&mut image);
+    Ok(image)
+}
+
+/// Like `render_image`, encoded as a PNG.
+pub fn render_png(token_address: &str, options: &RenderOptions) -> Result<Vec<u8>, RenderError> {
+    let image = render_image(token_address, options)?;
+    encode_png(&image).map_err(RenderError::Encode)
+}
+
+pub(crate) fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, ImageError> {
+    let mut buffer = Cursor::new(Vec::new());
+    image.write_to(&mut buffer, ImageOutputFormat::Png)?;
+    Ok(buffer.into_inner())
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    UnknownGenerator(UnknownGenerator),
+    /// The Bevy app couldn't be built with the config it was given.
+    Config(ConfigError),
+    Encode(ImageError),
+    /// The Bevy app couldn't capture the frame.
+    Capture(String),
+    /// The Bevy app has stopped, so it can't render anything else.
+    Stopped,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::UnknownGenerator(err) => write!(f, "{}", err),
+            RenderError::Config(err) => write!(f, "{}", err),
+            RenderError::Encode(err) => write!(f, "Failed to encode the image: {}", err),
+            RenderError::Capture(err) => write!(f, "Failed to capture the frame: {}", err),
+            RenderError::Stopped => write!(f, "The renderer has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
@@ -0,0 +1,406 @@
+//! The scene spec is a plain data description of a token's art: the palette, the
+//! mountain layers and their heights. Generating it makes all of the random choices
+//! for a token, and everything that draws the art (the Bevy app, the rasterizer,
+//! exporters) works from it rather than rolling dice of its own. Because it is just
+//! data it can also be serialized, diffed and stored without running a renderer.
+
+mod palettes;
+mod v1;
+mod v2;
+
+use bevy::log::info;
+use clap::ValueEnum;
+pub use palettes::{NamedPalette, PaletteColor, PaletteError, PaletteSet};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+pub(crate) type MyRng = ChaCha8Rng;
+
+/// The art is generated in scene units rather than pixels, so the same token looks
+/// the same at every resolution. The scene is always `SCENE_SIZE` units square and
+/// is scaled to fit the window (or image) when drawn. This matches the width of the
+/// images we publish, so at 2000px one scene unit is exactly one pixel. Changing this
+/// changes the art of every version.
+pub const SCENE_SIZE: f32 = 2000.;
+
+/// The version of the algorithm used to generate a scene. Once tokens have been
+/// minted with a version it is frozen, so their art never changes. To change the art
+/// for new tokens, add a new version rather than changing an existing one.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratorVersion {
+    /// The Aptos Ecosystem Summit 2024.
+    V1,
+    /// Adds a sun or moon, clouds and stars to the sky, fog between the mountains
+    /// and gradients to the sky and the mountains. Not minted yet.
+    V2,
+}
+
+impl fmt::Display for GeneratorVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorVersion::V1 => write!(f, "v1"),
+            GeneratorVersion::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SceneSpec {
+    pub token_address: String,
+
+    pub version: GeneratorVersion,
+
+    /// The seed derived from the token address. See `token_seed`.
+    pub seed: u64,
+
+    /// Where the RNG was left after generating the scene, in words. Scrolling the
+    /// mountains keeps drawing from the same RNG, so this lets us pick up exactly
+    /// where generation left off.
+    pub rng_word_pos: u128,
+
+    pub palette: Palette,
+
+    /// Where the colors were picked from, for versions that use palette sets.
+    #[serde(default)]
+    pub palette_info: Option<PaletteInfo>,
+
+    /// Everything drawn in the sky, behind the mountains.
+    #[serde(default)]
+    pub sky: SkySpec,
+
+    #[serde(default)]
+    pub fog: Option<FogSpec>,
+
+    /// The mountain layers, back to front.
+    pub layers: Vec<LayerSpec>,
+}
+
+impl SceneSpec {
+    /// The mountain layers and haze bands, sorted back to front.
+    pub fn foreground(&self) -> Vec<Foreground<'_>> {
+        let haze_bands = self.fog.iter().flat_map(|fog| &fog.haze_bands);
+        let mut foreground: Vec<_> = self
+            .layers
+            .iter()
+            .map(Foreground::Mountain)
+            .chain(haze_bands.map(Foreground::Haze))
+            .collect();
+        foreground.sort_by(|a, b| a.z().total_cmp(&b.z()));
+        foreground
+    }
+}
+
+/// Something drawn in front of the sky.
+#[derive(Clone, Copy, Debug)]
+pub enum Foreground<'a> {
+    Mountain(&'a LayerSpec),
+    Haze(&'a HazeBand),
+}
+
+impl Foreground<'_> {
+    pub fn z(&self) -> f32 {
+        match self {
+            Foreground::Mountain(layer) => layer.z,
+            Foreground::Haze(band) => band.z,
+        }
+    }
+}
+
+/// The parts of a scene that differ between versions of the generation algorithm.
+struct Generated {
+    palette: Palette,
+    palette_info: Option<PaletteInfo>,
+    sky: SkySpec,
+    fog: Option<FogSpec>,
+    layers: Vec<LayerSpec>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Palette {
+    pub sky: SceneColor,
+    pub mountain_base: SceneColor,
+}
+
+/// The names of the palette set, palette and colors a scene's colors came from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PaletteInfo {
+    pub palette_set: String,
+    pub palette: String,
+    pub sky: Option<String>,
+    pub mountain_base: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LayerSpec {
+    /// The z-order of the layer. Layers further forward have a higher z, and so
+    /// also scroll faster.
+    pub z: f32,
+
+    pub color: SceneColor,
+
+    /// The height of the ridgeline in scene units, one sample per scene unit
+    /// starting from the left edge of the scene.
+    pub heights: Vec<f32>,
+
+    /// The state of the height generator after generating `heights`, for
+    /// generating more heights as the layer scrolls.
+    pub generator: HeightGenerator,
+
+    /// Shading from the ridgeline down to the base. Without it the layer is `color`
+    /// all over.
+    #[serde(default)]
+    pub shade: Option<VerticalGradient>,
+}
+
+impl LayerSpec {
+    /// The color of the layer at the given altitude, in scene units from the bottom
+    /// of the scene.
+    pub fn color_at(&self, altitude: f32) -> SceneColor {
+        self.shade
+            .as_ref()
+            .map_or(self.color, |shade| shade.color_at(altitude))
+    }
+}
+
+/// A vertical gradient from `bottom_color` at the altitude `bottom` to `top_color` at
+/// `top`, with altitudes in scene units from the bottom of the scene. Below and above
+/// that it is the color at the nearer end.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct VerticalGradient {
+    pub bottom: f32,
+    pub bottom_color: SceneColor,
+    pub top: f32,
+    pub top_color: SceneColor,
+}
+
+impl VerticalGradient {
+    pub fn color_at(&self, altitude: f32) -> SceneColor {
+        let t = ((altitude - self.bottom) / (self.top - self.bottom)).clamp(0.0, 1.0);
+        v1::interpolate(self.top_color, self.bottom_color, t)
+    }
+}
+
+/// The things drawn in front of the sky color but behind the mountains. Unlike the
+/// mountain heights, positions here are in scene units from the top left of the
+/// scene, with y pointing down.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SkySpec {
+    /// A glow toward the horizon, drawn over the sky color.
+    #[serde(default)]
+    pub gradient: Option<VerticalGradient>,
+
+    pub celestial_body: Option<CelestialBody>,
+
+    /// Only generated for darker skies.
+    pub star_field: Option<StarField>,
+
+    /// The cloud layers, back to front.
+    pub cloud_layers: Vec<CloudLayer>,
+}
+
+/// Fog between the viewer and the mountains. The fog is denser the further away and
+/// the closer to the ground something is. How much fog each layer is behind is baked
+/// into the layer colors when the scene is generated, the haze bands then add the
+/// fog's height falloff on top, which a flat layer color can't show.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FogSpec {
+    pub color: SceneColor,
+
+    /// How much fog there is per unit of depth. Layers are one unit of depth apart.
+    pub density: f32,
+
+    /// How quickly the fog thins out going up, per scene unit.
+    pub height_falloff: f32,
+
+    /// Gradients of fog drawn between mountain layers, back to front.
+    pub haze_bands: Vec<HazeBand>,
+}
+
+impl FogSpec {
+    /// The opacity of the haze band at the given altitude, in scene units from the
+    /// bottom of the scene.
+    pub fn haze_alpha(&self, band: &HazeBand, altitude: f32) -> f32 {
+        band.opacity * (-self.height_falloff * altitude.max(0.0)).exp()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct HazeBand {
+    /// Between the z of the layers it is drawn between.
+    pub z: f32,
+
+    /// The opacity at the bottom of the scene.
+    pub opacity: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CelestialKind {
+    Sun,
+    Moon,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CelestialBody {
+    pub kind: CelestialKind,
+    pub circle: Circle,
+    pub color: SceneColor,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StarField {
+    pub color: SceneColor,
+    pub stars: Vec<Circle>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CloudLayer {
+    /// Like `LayerSpec::z`, this decides how fast the clouds drift. Cloud layers are
+    /// always behind the mountains, so this is between 0 and 1.
+    pub z: f32,
+
+    pub color: SceneColor,
+
+    pub clouds: Vec<Cloud>,
+}
+
+/// A cloud is a clump of overlapping circles ("puffs") around its center.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Cloud {
+    pub x: f32,
+    pub y: f32,
+
+    /// Relative to the center of the cloud.
+    pub puffs: Vec<Circle>,
+}
+
+impl Cloud {
+    /// How far the cloud reaches from its center horizontally, so we know when it has
+    /// drifted out of view.
+    pub fn half_width(&self) -> f32 {
+        self.puffs
+            .iter()
+            .map(|puff| puff.x.abs() + puff.radius)
+            .fold(0.0, f32::max)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Circle {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+/// A color in sRGB space, with components from 0 to 1.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SceneColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl SceneColor {
+    pub fn rgb_u8(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            r: r as f32 / u8::MAX as f32,
+            g: g as f32 / u8::MAX as f32,
+            b: b as f32 / u8::MAX as f32,
+            a: 1.0,
+        }
+    }
+
+    pub fn as_rgba_u8(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a].map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+impl From<SceneColor> for bevy::render::color::Color {
+    fn from(color: SceneColor) -> Self {
+        Self::rgba(color.r, color.g, color.b, color.a)
+    }
+}
+
+/// Convert the token address into the u64 we seed the RNG with.
+pub fn token_seed(token_address: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(token_address);
+    let result = hasher.finalize();
+    let first_eight_bytes = &result[0..8];
+    u64::from_be_bytes(first_eight_bytes.try_into().unwrap())
+}
+
+pub(crate) fn get_rng(seed: u64) -> MyRng {
+    // Build deterministic rng with seed.
+    MyRng::seed_from_u64(seed)
+}
+
+/// Generate the scene for the given token address with the given version of the
+/// generation algorithm, picking colors from the palette set. Version 1 has its own
+/// colors and ignores the palette set.
+pub fn generate_scene(
+    token_address: &str,
+    version: GeneratorVersion,
+    palettes: &PaletteSet,
+) -> SceneSpec {
+    let seed = token_seed(token_address);
+
+    info!(
+        "Token address: {} // Seed {} // Version {}",
+        token_address, seed, version
+    );
+
+    let mut rng = get_rng(seed);
+
+    let generated = match version {
+        GeneratorVersion::V1 => {
+            let (palette, layers) = v1::generate(&mut rng);
+            Generated {
+                palette,
+                palette_info: None,
+                sky: SkySpec::default(),
+                fog: None,
+                layers,
+            }
+        },
+        GeneratorVersion::V2 => v2::generate(&mut rng, palettes),
+    };
+
+    SceneSpec {
+        token_address: token_address.to_string(),
+        version,
+        seed,
+        rng_word_pos: rng.get_word_pos(),
+        palette: generated.palette,
+        palette_info: generated.palette_info,
+        sky: generated.sky,
+        fog: generated.fog,
+        layers: generated.layers,
+    }
+}
+
+/// The state of the height generator for a layer. Versions of the generation
+/// algorithm can share a generator, as long as it is frozen.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum HeightGenerator {
+    V1(v1::MountainHeightGenerator),
+}
+
+impl HeightGenerator {
+    pub(crate) fn next(&mut self, rng: &mut MyRng) -> Option<f32> {
+        match self {
+            HeightGenerator::V1(generator) => generator.next(rng),
+        }
+    }
+}
+
+impl From<v1::MountainHeightGenerator> for HeightGenerator {
+    fn from(generator: v1::MountainHeightGenerator) -> Self {
+        HeightGenerator::V1(generator)
+    }
+}
@@ -0,0 +1,156 @@
+//! Palettes are loaded from YAML rather than hard-coded, so new palettes can be
+//! shipped for an event without a code change. See `palettes/default.yaml` for the
+//! format. Generator versions from v2 onwards pick their colors from a palette set;
+//! v1 is frozen and keeps its own colors.
+
+use super::{MyRng, SceneColor};
+use once_cell::sync::Lazy;
+use rand::{distributions::WeightedIndex, prelude::Distribution};
+use serde::Deserialize;
+use std::{fmt, path::Path};
+
+static DEFAULT_PALETTES: Lazy<PaletteSet> = Lazy::new(|| {
+    PaletteSet::from_yaml(include_str!("../../palettes/default.yaml"))
+        .expect("The default palette set is invalid")
+});
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct PaletteSet {
+    pub name: String,
+    pub palettes: Vec<NamedPalette>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct NamedPalette {
+    pub name: String,
+
+    /// How likely this palette is to be picked, relative to the others in the set.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+
+    /// The sky colors to pick from.
+    pub sky: Vec<PaletteColor>,
+
+    /// The base colors for the mountains to pick from.
+    pub mountains: Vec<PaletteColor>,
+}
+
+/// A color in a palette. In YAML this is either just a hex string like "#ffca8c", or
+/// a map with a name, like `{ name: Peach, color: "#ffca8c" }`. The name is what
+/// shows up in the token's attributes, so it's worth giving one.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "RawPaletteColor")]
+pub struct PaletteColor {
+    pub name: Option<String>,
+    pub color: SceneColor,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPaletteColor {
+    Hex(String),
+    Named { name: String, color: String },
+}
+
+impl TryFrom<RawPaletteColor> for PaletteColor {
+    type Error = String;
+
+    fn try_from(raw: RawPaletteColor) -> Result<Self, Self::Error> {
+        let (name, hex) = match raw {
+            RawPaletteColor::Hex(hex) => (None, hex),
+            RawPaletteColor::Named { name, color } => (Some(name), color),
+        };
+        let color = parse_hex(&hex).ok_or_else(|| format!("Invalid color {}", hex))?;
+        Ok(PaletteColor { name, color })
+    }
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl Default for PaletteSet {
+    /// The palette set embedded in the binary.
+    fn default() -> Self {
+        DEFAULT_PALETTES.clone()
+    }
+}
+
+impl PaletteSet {
+    pub fn from_yaml(yaml: &str) -> Result<Self, PaletteError> {
+        let palette_set: PaletteSet = serde_yaml::from_str(yaml).map_err(PaletteError::Parse)?;
+        palette_set.validate()?;
+        Ok(palette_set)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PaletteError> {
+        Self::from_yaml(&std::fs::read_to_string(path).map_err(PaletteError::Io)?)
+    }
+
+    /// Load the palette set at the path, or the default palette set if there isn't one.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, PaletteError> {
+        match path {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), PaletteError> {
+        let invalid = |reason: String| Err(PaletteError::Invalid(reason));
+        if self.palettes.is_empty() {
+            return invalid(format!("Palette set {} has no palettes", self.name));
+        }
+        for palette in &self.palettes {
+            if !(palette.weight.is_finite() && palette.weight > 0.0) {
+                return invalid(format!(
+                    "Palette {} must have a positive weight",
+                    palette.name
+                ));
+            }
+            if palette.sky.is_empty() || palette.mountains.is_empty() {
+                return invalid(format!(
+                    "Palette {} needs at least one sky and one mountain color",
+                    palette.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pick a palette at random, weighted by the palettes' weights.
+    pub(crate) fn choose(&self, rng: &mut MyRng) -> &NamedPalette {
+        // The set was validated when it was loaded, so this can't fail.
+        let weights = WeightedIndex::new(self.palettes.iter().map(|palette| palette.weight))
+            .expect("Palette weights must be positive");
+        &self.palettes[weights.sample(rng)]
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    Parse(serde_yaml::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(f, "Failed to read palette set: {}", err),
+            PaletteError::Parse(err) => write!(f, "Failed to parse palette set: {}", err),
+            PaletteError::Invalid(reason) => write!(f, "Invalid palette set: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// Parse a color like "#ffca8c".
+fn parse_hex(hex: &str) -> Option<SceneColor> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(SceneColor::rgb_u8(channel(0)?, channel(2)?, channel(4)?))
+}
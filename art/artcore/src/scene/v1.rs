@@ -0,0 +1,154 @@
+//! Version 1 of the generation algorithm, used for the Aptos Ecosystem Summit 2024.
+//!
+//! This is frozen: every token minted with this version must keep looking exactly the
+//! same, forever. Do not change anything in this file, including the order and ranges
+//! of the RNG draws and the float math. To change the art, add a new version instead.
+
+use super::{LayerSpec, MyRng, Palette, SceneColor, SCENE_SIZE};
+use bevy::log::info;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[allow(dead_code)]
+fn rand_color(rng: &mut MyRng, r: Range<u8>, g: Range<u8>, b: Range<u8>) -> SceneColor {
+    SceneColor::rgb_u8(rng.gen_range(r), rng.gen_range(g), rng.gen_range(b))
+}
+
+pub(super) fn interpolate(left: SceneColor, right: SceneColor, left_weight: f32) -> SceneColor {
+    let right_weight = 1.0 - left_weight;
+
+    SceneColor {
+        r: left.r * left_weight + right.r * right_weight,
+        g: left.g * left_weight + right.g * right_weight,
+        b: left.b * left_weight + right.b * right_weight,
+        a: left.a * left_weight + right.a * right_weight,
+    }
+}
+
+pub(super) fn generate(rng: &mut MyRng) -> (Palette, Vec<LayerSpec>) {
+    // Generate sky color.
+    let sky_colors = [
+        SceneColor::rgb_u8(255, 202, 140),
+        SceneColor::rgb_u8(211, 255, 154),
+        SceneColor::rgb_u8(71, 224, 226),
+    ];
+    let sky_color = sky_colors[rng.gen_range(0..sky_colors.len())];
+
+    info!("Sky color: {:?}", sky_color);
+
+    // Generate fog color.
+    // let fog_color = rand_color(&mut rng, 1..255, 1..255, 1..255);
+
+    // TODO: Add stuff in sky.
+
+    // Get mountain color.
+    let mountain_colors = [
+        SceneColor::rgb_u8(129, 128, 85),
+        SceneColor::rgb_u8(167, 154, 93),
+        SceneColor::rgb_u8(191, 182, 129),
+        SceneColor::rgb_u8(230, 230, 230),
+        SceneColor::rgb_u8(132, 134, 135),
+    ];
+
+    let mountain_base_color = mountain_colors[rng.gen_range(0..mountain_colors.len())];
+
+    // Generate mountains back to front.
+    let mut layers = Vec::new();
+    let height = SCENE_SIZE as f64;
+    let num_mountains: u64 = rng.gen_range(4..7);
+    let base_max_height = height * 0.7;
+    // If this is close to 0, the heights of the mountains will be more similar.
+    let height_diff_multiplier = 0.7;
+    for i in 0..num_mountains {
+        let color = interpolate(
+            mountain_base_color,
+            sky_color,
+            (i + 1) as f32 / num_mountains as f32,
+        );
+        let min_height = -height * 2.0 / (num_mountains * (num_mountains - i)) as f64;
+
+        // Scale max_height based on z-order.
+        let max_height =
+            base_max_height * (1.0 - (i as f64 / num_mountains as f64 * height_diff_multiplier));
+        info!(
+            "Mountain {} min height {} max height: {}",
+            i, min_height, max_height
+        );
+
+        // Initialize the height generator
+        let mut generator = MountainHeightGenerator::new(min_height as f32, max_height as f32, rng);
+
+        // Generate initial heights
+        let mut heights: Vec<f32> = Vec::new();
+        for _ in 0..SCENE_SIZE as u32 * 2 {
+            heights.push(generator.next(rng).unwrap());
+        }
+
+        layers.push(LayerSpec {
+            z: (i + 1) as f32,
+            color,
+            heights,
+            generator: generator.into(),
+            shade: None,
+        });
+    }
+
+    let palette = Palette {
+        sky: sky_color,
+        mountain_base: mountain_base_color,
+    };
+
+    (palette, layers)
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MountainHeightGenerator {
+    height: f32,
+    slope: f32,
+    step_max: f32,
+    step_change: f32,
+    min_height: f32,
+    max_height: f32,
+}
+
+impl MountainHeightGenerator {
+    pub(super) fn new(min_height: f32, max_height: f32, rng: &mut MyRng) -> Self {
+        let step_max = rng.gen_range(0.9..1.1);
+        let step_change = rng.gen_range(0.15..0.35);
+        let height = rng.gen_range(0.0..max_height);
+        let slope = rng.gen_range(0.0..step_max) * 2.0 - step_max;
+
+        MountainHeightGenerator {
+            height,
+            slope,
+            step_max,
+            step_change,
+            min_height,
+            max_height,
+        }
+    }
+}
+
+impl MountainHeightGenerator {
+    pub(super) fn next(&mut self, rng: &mut MyRng) -> Option<f32> {
+        self.height += self.slope;
+        self.slope += rng.gen_range(0.0..self.step_change) * 2.0 - self.step_change;
+
+        if self.slope > self.step_max {
+            self.slope = self.step_max;
+        } else if self.slope < -self.step_max {
+            self.slope = -self.step_max;
+        }
+
+        if self.height > self.max_height {
+            self.height = self.max_height;
+            self.slope *= -1.0;
+        } else if self.height < self.min_height {
+            self.height = self.min_height;
+            self.slope *= -1.0;
+        }
+
+        Some(self.height)
+    }
+}
@@ -0,0 +1,300 @@
+//! Version 2 of the generation algorithm. This adds things to the sky: a sun or a
+//! moon, layers of clouds and, for darker skies, stars. The mountains are generated
+//! the same way as in v1, but their colors come from fog rather than fading each
+//! layer toward the sky color, the sky and mountain colors come from a palette set
+//! rather than being hard-coded, and the sky and the layers are gradients.
+//!
+//! No tokens have been minted with this version yet, so it can still change. Once
+//! they have, it is frozen just like v1.
+
+use super::{
+    v1::{interpolate, MountainHeightGenerator},
+    CelestialBody, CelestialKind, Circle, Cloud, CloudLayer, FogSpec, Generated, HazeBand,
+    LayerSpec, MyRng, Palette, PaletteInfo, PaletteSet, SceneColor, SkySpec, StarField,
+    VerticalGradient, SCENE_SIZE,
+};
+use bevy::log::info;
+use rand::Rng;
+
+/// Skies with a relative luminance below this are dark enough for stars, and get a
+/// moon rather than a sun.
+const DARK_SKY_LUMINANCE: f32 = 0.25;
+
+pub(super) fn generate(rng: &mut MyRng, palettes: &PaletteSet) -> Generated {
+    let chosen = palettes.choose(rng);
+
+    // Generate sky color.
+    let sky = &chosen.sky[rng.gen_range(0..chosen.sky.len())];
+    let sky_color = sky.color;
+    let dark = luminance(sky_color) < DARK_SKY_LUMINANCE;
+
+    info!(
+        "Palette: {}/{} // Sky color: {:?} // Dark: {}",
+        palettes.name, chosen.name, sky_color, dark
+    );
+
+    let mut sky_spec = SkySpec {
+        gradient: None,
+        star_field: dark.then(|| generate_star_field(rng)),
+        celestial_body: generate_celestial_body(rng, dark),
+        cloud_layers: generate_cloud_layers(rng, sky_color, dark),
+    };
+
+    // Generate the fog. It's tinted toward the sky color so it looks like it belongs,
+    // more so for darker skies so the fog doesn't glow.
+    let fog_tint = SceneColor::rgb_u8(
+        rng.gen_range(150..=255),
+        rng.gen_range(150..=255),
+        rng.gen_range(150..=255),
+    );
+    let fog_color = interpolate(fog_tint, sky_color, if dark { 0.25 } else { 0.5 });
+    let density = rng.gen_range(0.25..0.6);
+    let height_falloff = rng.gen_range(0.0005..0.0012);
+
+    info!(
+        "Fog color: {:?} // Density: {} // Height falloff: {}",
+        fog_color, density, height_falloff
+    );
+
+    // Get mountain color.
+    let mountain_base = &chosen.mountains[rng.gen_range(0..chosen.mountains.len())];
+    let mountain_base_color = mountain_base.color;
+
+    // Generate mountains back to front.
+    let mut layers = Vec::new();
+    let height = SCENE_SIZE as f64;
+    let num_mountains: u64 = rng.gen_range(4..7);
+    let base_max_height = height * 0.7;
+    // If this is close to 0, the heights of the mountains will be more similar.
+    let height_diff_multiplier = 0.7;
+    for i in 0..num_mountains {
+        let min_height = -height * 2.0 / (num_mountains * (num_mountains - i)) as f64;
+
+        // Scale max_height based on z-order.
+        let max_height =
+            base_max_height * (1.0 - (i as f64 / num_mountains as f64 * height_diff_multiplier));
+
+        let mut generator = MountainHeightGenerator::new(min_height as f32, max_height as f32, rng);
+
+        let mut heights: Vec<f32> = Vec::new();
+        for _ in 0..SCENE_SIZE as u32 * 2 {
+            heights.push(generator.next(rng).unwrap());
+        }
+
+        // The fog between the viewer and the layer. Layers further back are behind more
+        // fog, but lower layers are in thicker fog than higher ones.
+        let depth = (num_mountains - 1 - i) as f32;
+        let altitude = mean_altitude(&heights);
+        let fog_amount = 1.0 - (-density * depth * (-height_falloff * altitude).exp()).exp();
+        let color = interpolate(fog_color, mountain_base_color, fog_amount);
+
+        layers.push(LayerSpec {
+            z: (i + 1) as f32,
+            color,
+            heights,
+            generator: generator.into(),
+            shade: None,
+        });
+    }
+
+    // Maybe put a band of haze between each pair of layers.
+    let mut haze_bands = Vec::new();
+    for pair in layers.windows(2) {
+        if rng.gen_bool(0.5) {
+            haze_bands.push(HazeBand {
+                z: (pair[0].z + pair[1].z) / 2.0,
+                opacity: rng.gen_range(0.2..0.5),
+            });
+        }
+    }
+
+    // These were added after everything above, so they are drawn last to leave the
+    // rest of the scene as it was.
+    sky_spec.gradient = Some(generate_horizon_glow(rng, sky_color, dark));
+    for layer in &mut layers {
+        layer.shade = Some(generate_shade(rng, layer));
+    }
+
+    let fog = FogSpec {
+        color: fog_color,
+        density,
+        height_falloff,
+        haze_bands,
+    };
+
+    let palette = Palette {
+        sky: sky_color,
+        mountain_base: mountain_base_color,
+    };
+
+    let palette_info = PaletteInfo {
+        palette_set: palettes.name.clone(),
+        palette: chosen.name.clone(),
+        sky: sky.name.clone(),
+        mountain_base: mountain_base.name.clone(),
+    };
+
+    Generated {
+        palette,
+        palette_info: Some(palette_info),
+        sky: sky_spec,
+        fog: Some(fog),
+        layers,
+    }
+}
+
+/// A glow toward the horizon, from a warm tint low down to the sky color higher up.
+/// Like the fog it is tinted toward the sky color, more so for darker skies.
+fn generate_horizon_glow(rng: &mut MyRng, sky_color: SceneColor, dark: bool) -> VerticalGradient {
+    let tint = SceneColor::rgb_u8(
+        rng.gen_range(200..=255),
+        rng.gen_range(150..=230),
+        rng.gen_range(120..=200),
+    );
+    let strength = if dark {
+        rng.gen_range(0.15..0.3)
+    } else {
+        rng.gen_range(0.3..0.6)
+    };
+
+    VerticalGradient {
+        bottom: SCENE_SIZE * rng.gen_range(0.2..0.4),
+        bottom_color: interpolate(tint, sky_color, strength),
+        top: SCENE_SIZE * rng.gen_range(0.7..1.0),
+        top_color: sky_color,
+    }
+}
+
+/// Shade the layer from its color at its highest peak to a darker base at the bottom
+/// of the scene, as if lit from above.
+fn generate_shade(rng: &mut MyRng, layer: &LayerSpec) -> VerticalGradient {
+    let darkness = rng.gen_range(0.1..0.35);
+    let base = SceneColor {
+        r: layer.color.r * (1.0 - darkness),
+        g: layer.color.g * (1.0 - darkness),
+        b: layer.color.b * (1.0 - darkness),
+        a: layer.color.a,
+    };
+
+    let peak = layer.heights[..SCENE_SIZE as usize]
+        .iter()
+        .fold(f32::MIN, |peak, height| peak.max(*height));
+
+    VerticalGradient {
+        bottom: 0.0,
+        bottom_color: base,
+        top: peak + SCENE_SIZE / 6.0,
+        top_color: layer.color,
+    }
+}
+
+/// The average altitude of the visible part of the layer, in scene units from the
+/// bottom of the scene. Heights are measured from a baseline a sixth of the way up.
+fn mean_altitude(heights: &[f32]) -> f32 {
+    let visible = &heights[..SCENE_SIZE as usize];
+    visible.iter().sum::<f32>() / visible.len() as f32 + SCENE_SIZE / 6.0
+}
+
+fn generate_star_field(rng: &mut MyRng) -> StarField {
+    // Stars only go in the top of the sky, the mountains cover the rest anyway.
+    let num_stars = rng.gen_range(80..200);
+    let stars = (0..num_stars)
+        .map(|_| Circle {
+            x: rng.gen_range(0.0..SCENE_SIZE),
+            y: rng.gen_range(0.0..SCENE_SIZE * 0.6),
+            radius: rng.gen_range(1.5..4.0),
+        })
+        .collect();
+
+    StarField {
+        color: SceneColor::rgb_u8(255, 252, 235),
+        stars,
+    }
+}
+
+fn generate_celestial_body(rng: &mut MyRng, dark: bool) -> Option<CelestialBody> {
+    if !rng.gen_bool(0.8) {
+        return None;
+    }
+
+    let (kind, colors) = if dark {
+        (CelestialKind::Moon, [
+            SceneColor::rgb_u8(240, 240, 228),
+            SceneColor::rgb_u8(220, 226, 238),
+        ])
+    } else {
+        (CelestialKind::Sun, [
+            SceneColor::rgb_u8(255, 244, 200),
+            SceneColor::rgb_u8(255, 226, 160),
+        ])
+    };
+
+    Some(CelestialBody {
+        kind,
+        circle: Circle {
+            x: rng.gen_range(SCENE_SIZE * 0.15..SCENE_SIZE * 0.85),
+            y: rng.gen_range(SCENE_SIZE * 0.1..SCENE_SIZE * 0.4),
+            radius: rng.gen_range(60.0..160.0),
+        },
+        color: colors[rng.gen_range(0..colors.len())],
+    })
+}
+
+fn generate_cloud_layers(rng: &mut MyRng, sky_color: SceneColor, dark: bool) -> Vec<CloudLayer> {
+    let cloud_base_color = if dark {
+        SceneColor::rgb_u8(96, 100, 126)
+    } else {
+        SceneColor::rgb_u8(255, 255, 255)
+    };
+
+    let num_layers = rng.gen_range(0..4);
+    (0..num_layers)
+        .map(|i| {
+            // Clouds further back are closer to the sky color, like the mountains.
+            let color = interpolate(cloud_base_color, sky_color, 0.35 + 0.15 * i as f32);
+            let num_clouds = rng.gen_range(2..6);
+            let clouds = (0..num_clouds).map(|_| generate_cloud(rng)).collect();
+
+            CloudLayer {
+                z: 0.3 + 0.2 * i as f32,
+                color,
+                clouds,
+            }
+        })
+        .collect()
+}
+
+fn generate_cloud(rng: &mut MyRng) -> Cloud {
+    let half_width = rng.gen_range(80.0..220.0);
+    let num_puffs = rng.gen_range(4..9);
+    let puffs = (0..num_puffs)
+        .map(|_| {
+            let x: f32 = rng.gen_range(-half_width..half_width);
+            // Puffs in the middle of the cloud are bigger, which gives it a rounded top.
+            let middle = 1.0 - (x / half_width).abs();
+            Circle {
+                x,
+                y: rng.gen_range(-20.0..20.0),
+                radius: rng.gen_range(30.0..50.0) + 40.0 * middle,
+            }
+        })
+        .collect();
+
+    Cloud {
+        x: rng.gen_range(0.0..SCENE_SIZE),
+        y: rng.gen_range(SCENE_SIZE * 0.1..SCENE_SIZE * 0.5),
+        puffs,
+    }
+}
+
+/// The relative luminance of the color, from 0 to 1.
+fn luminance(color: SceneColor) -> f32 {
+    let linear = |value: f32| {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
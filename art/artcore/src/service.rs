@@ -0,0 +1,74 @@
+//! Run the Bevy app as a service that async code can ask for images. The app runs on
+//! a thread of its own, so the caller's main thread is free for its runtime, and the
+//! app is just another component of the binary.
+
+use super::{ApiChannels, AppConfig, RenderError, RenderJob, RenderRequest, TokenAddressReceiver};
+use std::sync::Arc;
+use tokio::sync::{oneshot, Semaphore};
+
+/// How many requests the app can have waiting to be rendered. Any more wait in
+/// `RenderService::render` until there is room.
+const QUEUE_SIZE: usize = 8;
+
+/// A handle to the Bevy app rendering on its own thread. Clones share the app.
+#[derive(Clone, Debug)]
+pub struct RenderService {
+    // The app polls for jobs every frame, so this doesn't need to be async.
+    jobs: crossbeam_channel::Sender<RenderJob>,
+    queue: Arc<Semaphore>,
+}
+
+impl RenderService {
+    /// Build the app and run it on a thread of its own. The config is loaded before
+    /// the thread is started, and this waits for the app to be built, so bad config
+    /// fails here.
+    pub fn start(config: AppConfig) -> Result<Self, RenderError> {
+        // Callers that give up leave their jobs behind, so the queue can briefly be
+        // longer than `QUEUE_SIZE`. The app skips those jobs.
+        let (job_sender, job_receiver) = crossbeam_channel::unbounded();
+        let (built_sender, built_receiver) = std::sync::mpsc::channel();
+
+        let plugin = config
+            .api_plugin(ApiChannels {
+                token_address_receiver: TokenAddressReceiver {
+                    receiver: job_receiver,
+                },
+            })
+            .map_err(RenderError::Config)?;
+
+        std::thread::Builder::new()
+            .name("renderer".to_string())
+            .spawn(move || {
+                let mut app = config.app(plugin, None);
+                // If building the app panicked we never get here, and `start` sees
+                // this was dropped.
+                built_sender.send(()).ok();
+                app.run();
+            })
+            .expect("Failed to spawn the renderer thread");
+
+        built_receiver.recv().map_err(|_| RenderError::Stopped)?;
+
+        Ok(Self {
+            jobs: job_sender,
+            queue: Arc::new(Semaphore::new(QUEUE_SIZE)),
+        })
+    }
+
+    /// Render the art and encode it as a PNG.
+    pub async fn render(&self, request: RenderRequest) -> Result<Vec<u8>, RenderError> {
+        let _permit = self
+            .queue
+            .acquire()
+            .await
+            .map_err(|_| RenderError::Stopped)?;
+
+        let (reply, image) = oneshot::channel();
+        self.jobs
+            .send(RenderJob { request, reply })
+            .map_err(|_| RenderError::Stopped)?;
+
+        // If the app drops the reply without answering, it has stopped.
+        image.await.map_err(|_| RenderError::Stopped)?
+    }
+}
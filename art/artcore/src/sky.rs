@@ -0,0 +1,168 @@
+//! Spawning and animating the things in the sky: the glow toward the horizon, the sun
+//! or moon, the stars and the clouds. These are all drawn behind the mountains, the
+//! mountain layers start at a z of 1.
+
+use super::{fog::vertical_gradient, parallax_speed, Circle, PauseState, SkySpec, SCENE_SIZE};
+use bevy::{
+    prelude::*,
+    sprite::MaterialMesh2dBundle,
+    window::{PrimaryWindow, WindowResolution},
+};
+use bevy_prototype_lyon::prelude::*;
+
+/// How many rows the horizon glow is split in to, like the haze bands.
+const GRADIENT_ROWS: usize = 32;
+
+const GRADIENT_Z: f32 = 0.01;
+const STAR_FIELD_Z: f32 = 0.05;
+const CELESTIAL_BODY_Z: f32 = 0.1;
+
+/// Marks everything spawned for the sky, so it can be despawned with the mountains.
+#[derive(Component)]
+pub(crate) struct SkyElement;
+
+/// A cloud drifting to the left. When it has drifted all the way out of view it
+/// wraps around to the right again.
+#[derive(Component)]
+pub(crate) struct DriftingCloud {
+    /// The center of the cloud, in scene units from the left edge of the scene.
+    x: f32,
+    half_width: f32,
+    z: f32,
+}
+
+/// Convert a position in scene units from the top left of the scene to a position in
+/// the world, which has its origin in the middle of the window and y pointing up.
+fn to_world(x: f32, y: f32, resolution: &WindowResolution) -> Vec2 {
+    let scale = resolution.width() / SCENE_SIZE;
+    Vec2::new(
+        x * scale - resolution.width() / 2.,
+        resolution.height() / 2. - y * scale,
+    )
+}
+
+/// Build one path out of all the circles. The circles are in scene units relative to
+/// the entity's transform, with y pointing down.
+fn circles_path(circles: &[Circle], resolution: &WindowResolution) -> Path {
+    let scale = resolution.width() / SCENE_SIZE;
+    circles
+        .iter()
+        .fold(GeometryBuilder::new(), |builder, circle| {
+            builder.add(&shapes::Circle {
+                radius: circle.radius * scale,
+                center: Vec2::new(circle.x * scale, -circle.y * scale),
+            })
+        })
+        .build()
+}
+
+pub(crate) fn spawn_sky(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    sky: &SkySpec,
+    resolution: &WindowResolution,
+) {
+    if let Some(gradient) = &sky.gradient {
+        let colors = (0..=GRADIENT_ROWS)
+            .map(|row| row as f32 / GRADIENT_ROWS as f32 * SCENE_SIZE)
+            .map(|altitude| Color::from(gradient.color_at(altitude)))
+            .collect::<Vec<_>>();
+        let mesh = vertical_gradient(resolution.width(), resolution.height(), &colors);
+        commands.spawn((SkyElement, MaterialMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            // The vertex colors are multiplied by the material color, so leave it white.
+            material: materials.add(ColorMaterial::default()),
+            transform: Transform::from_xyz(0.0, -resolution.height() / 2.0, GRADIENT_Z),
+            ..default()
+        }));
+    }
+
+    if let Some(star_field) = &sky.star_field {
+        let origin = to_world(0., 0., resolution);
+        commands.spawn((
+            SkyElement,
+            ShapeBundle {
+                path: circles_path(&star_field.stars, resolution),
+                spatial: SpatialBundle::from_transform(Transform::from_xyz(
+                    origin.x,
+                    origin.y,
+                    STAR_FIELD_Z,
+                )),
+                ..default()
+            },
+            Fill::color(Color::from(star_field.color)),
+        ));
+    }
+
+    if let Some(body) = &sky.celestial_body {
+        let center = to_world(body.circle.x, body.circle.y, resolution);
+        let radius = body.circle.radius * resolution.width() / SCENE_SIZE;
+        commands.spawn((
+            SkyElement,
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shapes::Circle {
+                    radius,
+                    center: Vec2::ZERO,
+                }),
+                spatial: SpatialBundle::from_transform(Transform::from_xyz(
+                    center.x,
+                    center.y,
+                    CELESTIAL_BODY_Z,
+                )),
+                ..default()
+            },
+            Fill::color(Color::from(body.color)),
+        ));
+    }
+
+    for layer in &sky.cloud_layers {
+        for cloud in &layer.clouds {
+            let center = to_world(cloud.x, cloud.y, resolution);
+            commands.spawn((
+                SkyElement,
+                DriftingCloud {
+                    x: cloud.x,
+                    half_width: cloud.half_width(),
+                    z: layer.z,
+                },
+                ShapeBundle {
+                    path: circles_path(&cloud.puffs, resolution),
+                    spatial: SpatialBundle::from_transform(Transform::from_xyz(
+                        center.x, center.y, layer.z,
+                    )),
+                    ..default()
+                },
+                Fill::color(Color::from(layer.color)),
+            ));
+        }
+    }
+}
+
+/// Drift the clouds at the same speed the mountains at the same z would scroll at, so
+/// the parallax is consistent all the way back.
+pub(crate) fn update_clouds(
+    time: Res<Time>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut query: Query<(&mut DriftingCloud, &mut Transform)>,
+    pause_state: Res<PauseState>,
+) {
+    if pause_state.paused {
+        return;
+    }
+
+    let window = window.single();
+    let resolution = &window.resolution;
+
+    for (mut cloud, mut transform) in query.iter_mut() {
+        cloud.x -= parallax_speed(cloud.z) * time.delta_seconds();
+
+        // Once it's completely out of view on the left, move it to just out of view
+        // on the right.
+        if cloud.x + cloud.half_width < 0. {
+            cloud.x += SCENE_SIZE + 2. * cloud.half_width;
+        }
+
+        transform.translation.x = to_world(cloud.x, 0., resolution).x;
+    }
+}
@@ -0,0 +1,274 @@
+//! Export a scene as a standalone SVG. This draws the same thing as the rasterizer,
+//! the sky, one filled path per mountain layer and the haze between them, but as
+//! vectors, so it can be printed or displayed at any size.
+
+use super::{
+    generate_scene, ridge_segments, BlendMode, Circle, FogSpec, Foreground, GeneratorVersion,
+    HazeBand, LayerSpec, OverlayRect, PaletteSet, PathStyle, SceneColor, SceneSpec, Segment,
+    SkySpec, VerticalGradient, SCENE_SIZE,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bevy::math::Vec2;
+use std::fmt::Write;
+
+#[derive(Clone, Debug)]
+pub struct SvgOptions<'a> {
+    /// The width (and height) of the SVG. This is just the size it is displayed at,
+    /// the SVG itself is drawn in scene units.
+    pub width: u32,
+
+    /// Images to draw over the art, in order.
+    pub overlays: Vec<SvgOverlay<'a>>,
+
+    /// How to draw the ridgelines. Smooth paths make for a much smaller SVG.
+    pub path_style: PathStyle,
+}
+
+/// An image drawn over the art, like the texture or the lockup.
+#[derive(Clone, Debug)]
+pub struct SvgOverlay<'a> {
+    /// PNG data for the image.
+    pub png_data: &'a [u8],
+
+    /// Where to draw it. The image is stretched to fill this.
+    pub rect: OverlayRect,
+
+    pub blend: BlendMode,
+
+    pub opacity: f32,
+}
+
+/// Export the art for the given token address as an SVG.
+pub fn token_to_svg(
+    token_address: &str,
+    version: GeneratorVersion,
+    palettes: &PaletteSet,
+    options: &SvgOptions,
+) -> String {
+    scene_to_svg(&generate_scene(token_address, version, palettes), options)
+}
+
+/// Export the scene as an SVG.
+pub fn scene_to_svg(scene: &SceneSpec, options: &SvgOptions) -> String {
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{width}" viewBox="0 0 {size} {size}">"#,
+        width = options.width,
+        size = SCENE_SIZE,
+    )
+    .unwrap();
+
+    // The sky.
+    writeln!(
+        svg,
+        r#"<rect width="{size}" height="{size}" {fill}/>"#,
+        size = SCENE_SIZE,
+        fill = fill(scene.palette.sky),
+    )
+    .unwrap();
+
+    write_sky(&mut svg, &scene.sky);
+
+    // The foreground is ordered back to front, so we can just draw it in order.
+    for (i, element) in scene.foreground().into_iter().enumerate() {
+        match element {
+            Foreground::Mountain(layer) => {
+                let fill = match &layer.shade {
+                    Some(shade) => {
+                        let id = format!("shade{}", i);
+                        write_gradient(&mut svg, shade, &id);
+                        format!(r#"fill="url(#{})""#, id)
+                    },
+                    None => fill(layer.color),
+                };
+                writeln!(
+                    svg,
+                    r#"<path d="{path}" {fill}/>"#,
+                    path = layer_path(layer, options.path_style),
+                )
+                .unwrap();
+            },
+            Foreground::Haze(band) => {
+                let id = format!("haze{}", i);
+                write_haze(&mut svg, scene.fog.as_ref().unwrap(), band, &id);
+            },
+        }
+    }
+
+    for overlay in &options.overlays {
+        writeln!(svg, "{}", image(overlay)).unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Draw the stars, the sun or moon and the clouds, as they start out before the
+/// clouds drift.
+fn write_sky(svg: &mut String, sky: &SkySpec) {
+    if let Some(gradient) = &sky.gradient {
+        write_gradient(svg, gradient, "sky");
+        writeln!(
+            svg,
+            r#"<rect width="{size}" height="{size}" fill="url(#sky)"/>"#,
+            size = SCENE_SIZE,
+        )
+        .unwrap();
+    }
+
+    if let Some(star_field) = &sky.star_field {
+        writeln!(svg, "<g {}>", fill(star_field.color)).unwrap();
+        for star in &star_field.stars {
+            writeln!(svg, "{}", circle(star, 0.0, 0.0)).unwrap();
+        }
+        svg.push_str("</g>\n");
+    }
+
+    if let Some(body) = &sky.celestial_body {
+        writeln!(svg, "<g {}>", fill(body.color)).unwrap();
+        writeln!(svg, "{}", circle(&body.circle, 0.0, 0.0)).unwrap();
+        svg.push_str("</g>\n");
+    }
+
+    for layer in &sky.cloud_layers {
+        writeln!(svg, "<g {}>", fill(layer.color)).unwrap();
+        for cloud in &layer.clouds {
+            for puff in &cloud.puffs {
+                writeln!(svg, "{}", circle(puff, cloud.x, cloud.y)).unwrap();
+            }
+        }
+        svg.push_str("</g>\n");
+    }
+}
+
+/// Draw a haze band as a rect with a gradient running from the bottom of the scene
+/// to the top. The opacity falls off exponentially, so sample it at a few stops.
+fn write_haze(svg: &mut String, fog: &FogSpec, band: &HazeBand, id: &str) {
+    const STOPS: usize = 32;
+
+    let [r, g, b, _] = fog.color.as_rgba_u8();
+    writeln!(
+        svg,
+        r#"<linearGradient id="{id}" x1="0" y1="1" x2="0" y2="0">"#
+    )
+    .unwrap();
+    for stop in 0..=STOPS {
+        let offset = stop as f32 / STOPS as f32;
+        writeln!(
+            svg,
+            r##"<stop offset="{:.4}" stop-color="#{:02x}{:02x}{:02x}" stop-opacity="{:.4}"/>"##,
+            offset,
+            r,
+            g,
+            b,
+            fog.haze_alpha(band, offset * SCENE_SIZE) * fog.color.a,
+        )
+        .unwrap();
+    }
+    svg.push_str("</linearGradient>\n");
+
+    writeln!(
+        svg,
+        r#"<rect width="{size}" height="{size}" fill="url(#{id})"/>"#,
+        size = SCENE_SIZE,
+    )
+    .unwrap();
+}
+
+/// Define a gradient with the given id. It is in scene units, so the altitudes line
+/// up with the rest of the scene whatever it fills.
+fn write_gradient(svg: &mut String, gradient: &VerticalGradient, id: &str) {
+    writeln!(
+        svg,
+        r#"<linearGradient id="{id}" gradientUnits="userSpaceOnUse" x1="0" y1="{bottom:.2}" x2="0" y2="{top:.2}">"#,
+        bottom = SCENE_SIZE - gradient.bottom,
+        top = SCENE_SIZE - gradient.top,
+    )
+    .unwrap();
+    for (offset, color) in [(0, gradient.bottom_color), (1, gradient.top_color)] {
+        let [r, g, b, _] = color.as_rgba_u8();
+        writeln!(
+            svg,
+            r##"<stop offset="{}" stop-color="#{:02x}{:02x}{:02x}" stop-opacity="{}"/>"##,
+            offset, r, g, b, color.a,
+        )
+        .unwrap();
+    }
+    svg.push_str("</linearGradient>\n");
+}
+
+fn circle(circle: &Circle, offset_x: f32, offset_y: f32) -> String {
+    format!(
+        r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}"/>"#,
+        circle.x + offset_x,
+        circle.y + offset_y,
+        circle.radius
+    )
+}
+
+/// Build the path data for a layer. This mirrors the geometry built by
+/// `Mountain::build_mesh` and the transform applied in `Mountain::spawn`, but with
+/// y pointing down like it does in SVG. We only include the samples that are
+/// actually visible, the rest are only there to scroll in to view.
+fn layer_path(layer: &LayerSpec, style: PathStyle) -> String {
+    // Where a height of 0 ends up, measured from the top of the scene.
+    let baseline = SCENE_SIZE / 2.0 + SCENE_SIZE / 3.0;
+
+    let points: Vec<Vec2> = layer
+        .heights
+        .iter()
+        .take(SCENE_SIZE as usize + 1)
+        .enumerate()
+        .map(|(i, height)| Vec2::new(i as f32, baseline - height))
+        .collect();
+
+    let mut path = format!("M0 {}", SCENE_SIZE);
+    if let Some(first) = points.first() {
+        write!(path, "L{} {:.2}", first.x, first.y).unwrap();
+    }
+    for segment in ridge_segments(&points, style) {
+        match segment {
+            Segment::Line(to) => write!(path, "L{} {:.2}", to.x, to.y),
+            Segment::Cubic { ctrl1, ctrl2, to } => write!(
+                path,
+                "C{:.2} {:.2} {:.2} {:.2} {} {:.2}",
+                ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y
+            ),
+        }
+        .unwrap();
+    }
+    write!(
+        path,
+        "L{} {}Z",
+        (layer.heights.len() - 1).min(SCENE_SIZE as usize),
+        SCENE_SIZE
+    )
+    .unwrap();
+
+    path
+}
+
+fn fill(color: SceneColor) -> String {
+    let [r, g, b, _] = color.as_rgba_u8();
+    let mut fill = format!(r##"fill="#{:02x}{:02x}{:02x}""##, r, g, b);
+    if color.a < 1.0 {
+        write!(fill, r#" fill-opacity="{}""#, color.a).unwrap();
+    }
+    fill
+}
+
+fn image(overlay: &SvgOverlay) -> String {
+    let rect = overlay.rect;
+    format!(
+        r#"<image x="{x:.2}" y="{y:.2}" width="{width:.2}" height="{height:.2}" preserveAspectRatio="none" opacity="{opacity}" style="mix-blend-mode:{blend_mode}" href="data:image/png;base64,{data}"/>"#,
+        x = rect.x * SCENE_SIZE,
+        y = rect.y * SCENE_SIZE,
+        width = rect.width * SCENE_SIZE,
+        height = rect.height * SCENE_SIZE,
+        opacity = overlay.opacity,
+        blend_mode = overlay.blend.css_name(),
+        data = STANDARD.encode(overlay.png_data),
+    )
+}
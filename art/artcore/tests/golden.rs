@@ -0,0 +1,186 @@
+//! Golden tests for deterministic generation. For every token address and generator
+//! version in the fixture file we check the seed we derive from the address, the
+//! heights generated for each mountain layer, the token's attributes and hashes of the
+//! rendered images. If any of these change then the art of tokens that already exist
+//! has changed, whether that's because of a code change, a dependency bump or
+//! different float math on the target. Everything is generated with the default
+//! palette set, so changing that changes the fixtures for v2 onwards too.
+//!
+//! The v1 fixtures also have the image we publish, at the width we publish at with
+//! the default overlays drawn over it, so the compositing is covered too.
+//!
+//! If a change to the art is intentional, regenerate the fixtures like this:
+//!
+//! GOLDEN_BLESS=1 cargo test -p artcore --all-features --test golden
+
+use artcore::{generate_scene, token_seed, GeneratorVersion, PaletteSet, TokenAttributes};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+#[cfg(feature = "raster")]
+use {
+    artcore::Overlays,
+    once_cell::sync::Lazy,
+    std::{path::Path, sync::Arc},
+};
+
+/// How many heights per layer we store in the fixtures verbatim. The rest are only
+/// covered by the hash, but having a few makes a failure much easier to read.
+const NUM_LEADING_HEIGHTS: usize = 8;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Fixture {
+    token_address: String,
+    version: GeneratorVersion,
+    seed: u64,
+    layers: Vec<LayerFixture>,
+    attributes: TokenAttributes,
+    images: Vec<ImageFixture>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct LayerFixture {
+    leading_heights: Vec<f32>,
+    heights_sha256: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct ImageFixture {
+    width: u32,
+    /// Whether the default overlays are drawn over the image.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    overlays: bool,
+    sha256: String,
+}
+
+fn fixtures_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden.json")
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Build the fixture for the token address from the current code. We take the image
+/// widths from the expected fixture, so the fixture file decides what we render.
+fn actual_fixture(expected: &Fixture) -> Fixture {
+    let scene = generate_scene(
+        &expected.token_address,
+        expected.version,
+        &PaletteSet::default(),
+    );
+
+    let layers = scene
+        .layers
+        .iter()
+        .map(|layer| {
+            let bytes: Vec<u8> = layer.heights.iter().flat_map(|h| h.to_le_bytes()).collect();
+            LayerFixture {
+                leading_heights: layer.heights[..NUM_LEADING_HEIGHTS].to_vec(),
+                heights_sha256: sha256_hex(&bytes),
+            }
+        })
+        .collect();
+
+    Fixture {
+        token_address: expected.token_address.clone(),
+        version: expected.version,
+        seed: token_seed(&expected.token_address),
+        layers,
+        attributes: TokenAttributes::from_scene(&scene),
+        images: actual_images(expected),
+    }
+}
+
+/// The overlays we publish with, loaded once for all the fixtures.
+#[cfg(feature = "raster")]
+static DEFAULT_OVERLAYS: Lazy<Arc<Overlays>> = Lazy::new(|| {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join(artcore::DEFAULT_OVERLAYS);
+    Arc::new(Overlays::load(&path).expect("Failed to load the default overlays"))
+});
+
+#[cfg(feature = "raster")]
+fn actual_images(expected: &Fixture) -> Vec<ImageFixture> {
+    expected
+        .images
+        .iter()
+        .map(|image| {
+            let overlays = if image.overlays {
+                DEFAULT_OVERLAYS.clone()
+            } else {
+                Arc::new(Overlays::none())
+            };
+            ImageFixture {
+                width: image.width,
+                overlays: image.overlays,
+                sha256: sha256_hex(
+                    &artcore::render_image(&expected.token_address, &artcore::RenderOptions {
+                        version: expected.version,
+                        width: Some(image.width),
+                        overlays,
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ),
+            }
+        })
+        .collect()
+}
+
+// Without the rasterizer we can't check the images, so just check everything else.
+#[cfg(not(feature = "raster"))]
+fn actual_images(expected: &Fixture) -> Vec<ImageFixture> {
+    expected
+        .images
+        .iter()
+        .map(|image| ImageFixture {
+            width: image.width,
+            overlays: image.overlays,
+            sha256: image.sha256.clone(),
+        })
+        .collect()
+}
+
+#[test]
+fn golden() {
+    let path = fixtures_path();
+    let expected: Vec<Fixture> =
+        serde_json::from_str(&std::fs::read_to_string(&path).expect("Failed to read fixtures"))
+            .expect("Failed to parse fixtures");
+
+    let actual: Vec<Fixture> = expected.iter().map(actual_fixture).collect();
+
+    if std::env::var("GOLDEN_BLESS").is_ok() {
+        let json = serde_json::to_string_pretty(&actual).unwrap();
+        std::fs::write(&path, json + "\n").expect("Failed to write fixtures");
+        return;
+    }
+
+    for (expected, actual) in expected.iter().zip(actual.iter()) {
+        assert_eq!(
+            expected.seed, actual.seed,
+            "Seed changed for {}",
+            expected.token_address
+        );
+        assert_eq!(
+            expected.layers, actual.layers,
+            "Heights changed for {} ({})",
+            expected.token_address, expected.version
+        );
+        assert_eq!(
+            expected.attributes, actual.attributes,
+            "Attributes changed for {} ({})",
+            expected.token_address, expected.version
+        );
+        assert_eq!(
+            expected.images, actual.images,
+            "Rendered image changed for {} ({})",
+            expected.token_address, expected.version
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! The overlays published with the 2024 summit must be drawn exactly like they were
+//! before overlays could be declared and blended in other ways, otherwise the images
+//! of tokens that already exist change. So we draw the shipped overlays over rendered
+//! tokens and compare every byte with what the original compositing drew.
+
+#![cfg(feature = "raster")]
+
+use artcore::{render_image, GeneratorVersion, Overlays, RenderOptions};
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+fn assets() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../assets")
+}
+
+fn load(path: &Path) -> RgbaImage {
+    image::open(path)
+        .unwrap_or_else(|err| panic!("Failed to load {}: {}", path.display(), err))
+        .to_rgba8()
+}
+
+/// How the texture used to be drawn, verbatim.
+fn blend_images_multiply(base_image: &mut RgbaImage, images: Vec<&RgbaImage>) {
+    for (x, y, pixel) in base_image.enumerate_pixels_mut() {
+        for image in &images {
+            let image_pixel = image.get_pixel(x, y);
+            for i in 0..3 {
+                let base_val = pixel.0[i] as u16;
+                let overlay_val = image_pixel.0[i] as u16;
+                pixel.0[i] = ((base_val * overlay_val) / 255) as u8;
+            }
+        }
+    }
+}
+
+/// How the lockup used to be drawn, verbatim.
+fn blend_images_replace(base_image: &mut RgbaImage, images: Vec<&RgbaImage>) {
+    for (x, y, pixel) in base_image.enumerate_pixels_mut() {
+        for image in &images {
+            let image_pixel = image.get_pixel(x, y);
+            for i in 0..3 {
+                let base_val = pixel.0[i] as u16;
+                let overlay_val = image_pixel.0[i] as u16;
+                let alpha = image_pixel.0[3] as u16;
+                pixel.0[i] = ((base_val * (255 - alpha) + overlay_val * alpha) / 255) as u8;
+            }
+        }
+    }
+}
+
+#[test]
+fn shipped_overlays_match_original_compositing() {
+    let overlays = Overlays::load(&assets().join("overlays.yaml")).unwrap();
+    let texture = load(&assets().join("aptos-ecosummit-2024_nft_texture.png"));
+    let lockup = load(&assets().join("aptos-ecosummit-2024_nft_lockup.png"));
+
+    for (token_address, version) in [
+        ("0x5", GeneratorVersion::V1),
+        ("0xabc", GeneratorVersion::V2),
+    ] {
+        // At the width we publish at, which is the size of the overlay images.
+        let art = render_image(token_address, &RenderOptions {
+            version,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut expected = art.clone();
+        blend_images_multiply(&mut expected, vec![&texture]);
+        blend_images_replace(&mut expected, vec![&lockup]);
+
+        let mut actual = art;
+        overlays.apply(&mut actual);
+
+        let differing = expected
+            .pixels()
+            .zip(actual.pixels())
+            .filter(|(expected, actual)| expected != actual)
+            .count();
+        assert_eq!(
+            differing, 0,
+            "Overlays drawn differently for {} ({})",
+            token_address, version
+        );
+    }
+}
@@ -3,5 +3,11 @@ use clap::Parser;
 
 fn main() {
     let app_config = AppConfig::parse();
-    app_config.build(None).run();
+    match app_config.build(None) {
+        Ok(mut app) => app.run(),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    }
 }
@@ -1,32 +1,85 @@
-use artcore::{ApiChannels, AppConfig, ImageChannel, TokenAddressReceiver};
+use anyhow::{bail, Context, Result};
+use artcore::{
+    render_png, AppConfig, ArtGenerator, GeneratorVersion, Overlays, PaletteSet, PathStyle,
+    RenderOptions, RenderRequest, RenderService,
+};
+use std::{path::PathBuf, sync::Arc};
 
+/// How the processor turns a token address into png data.
 #[derive(Debug)]
-pub struct BevyChannels {
-    pub token_address_sender: crossbeam_channel::Sender<String>,
-    pub img_data_receiver: crossbeam_channel::Receiver<Vec<u8>>,
+pub enum ImageRenderer {
+    /// Send the token address to the Bevy app and wait for it to send back the image.
+    Bevy(RenderService),
+    /// Render the image with the software rasterizer, no window or GPU required.
+    Cpu {
+        width: u32,
+        palettes: Arc<PaletteSet>,
+        overlays: Arc<Overlays>,
+    },
 }
 
-pub fn run_bevy_app(
+impl ImageRenderer {
+    pub async fn render(
+        &self,
+        generator: &Arc<dyn ArtGenerator>,
+        token_address: &str,
+        generator_version: GeneratorVersion,
+    ) -> Result<Vec<u8>> {
+        match self {
+            ImageRenderer::Bevy(service) => service
+                .render(RenderRequest {
+                    generator: generator.name().to_string(),
+                    token_address: token_address.to_string(),
+                    generator_version,
+                })
+                .await
+                .context("Failed to render image"),
+            ImageRenderer::Cpu {
+                width,
+                palettes,
+                overlays,
+            } => {
+                let options = RenderOptions {
+                    generator: generator.name().to_string(),
+                    version: generator_version,
+                    width: Some(*width),
+                    palettes: palettes.clone(),
+                    overlays: overlays.clone(),
+                    path_style: PathStyle::default(),
+                };
+                let token_address = token_address.to_string();
+                tokio::task::spawn_blocking(move || render_png(&token_address, &options))
+                    .await
+                    .context("Failed to render image")?
+                    .context("Failed to render image")
+            },
+        }
+    }
+}
+
+/// Start the Bevy app on a thread of its own.
+pub fn start_render_service(
     width: u32,
-    img_data_sender: crossbeam_channel::Sender<Vec<u8>>,
-    token_address_receiver: crossbeam_channel::Receiver<String>,
-) {
-    // TODO: Accept this from configuration.
+    generator: String,
+    generator_version: GeneratorVersion,
+    palettes: Option<PathBuf>,
+    overlays: Option<PathBuf>,
+    window: bool,
+) -> Result<RenderService> {
+    if window && !cfg!(feature = "window") {
+        bail!("bevy_window needs the processor built with the window feature");
+    }
     let app_config = AppConfig {
-        width: width as f32,
+        width: Some(width as f32),
+        generator,
         initial_token_address: "0x5".to_string(),
+        generator_version,
+        palettes,
+        smooth_paths: None,
+        overlays,
         paused: true,
+        offscreen: !window,
     };
 
-    let mut bevy_app = app_config.build_for_api(None, ApiChannels {
-        image_channel: ImageChannel {
-            sender: img_data_sender,
-        },
-        token_address_receiver: TokenAddressReceiver {
-            receiver: token_address_receiver,
-        },
-    });
-
-    // Run the app.
-    bevy_app.run();
+    RenderService::start(app_config).context("Failed to start the Bevy app")
 }
@@ -15,6 +15,10 @@ use std::{fmt::Debug, path::PathBuf, sync::Arc};
 pub trait BlobStoreTrait: Debug + Send + Sync + 'static {
     /// Write the image to storage. The image should be in png format.
     async fn write_image(&self, png_data: Vec<u8>, token_address: &str) -> Result<()>;
+
+    /// Write the token's attributes to storage. These should be JSON in the standard
+    /// NFT metadata shape.
+    async fn write_attributes(&self, json_data: Vec<u8>, token_address: &str) -> Result<()>;
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -48,6 +52,17 @@ impl BlobStoreTrait for LocalBlobStore {
 
         Ok(())
     }
+
+    async fn write_attributes(&self, json_data: Vec<u8>, token_address: &str) -> Result<()> {
+        let filename = format!("{}.json", token_address);
+
+        std::fs::write(self.config.out_dir.join(filename), json_data).context(format!(
+            "Failed to write attributes for {} to disk",
+            token_address
+        ))?;
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -113,6 +128,34 @@ impl BlobStoreTrait for GcsBlobStore {
 
         Ok(())
     }
+
+    async fn write_attributes(&self, json_data: Vec<u8>, token_address: &str) -> Result<()> {
+        let filename = format!("attributes/{}.json", token_address);
+        let upload_type = UploadType::Multipart(Box::new(Object {
+            name: filename.clone(),
+            content_type: "application/json".to_string().into(),
+            size: json_data.len() as i64,
+            ..Default::default()
+        }));
+        self.client
+            .upload_object(
+                &UploadObjectRequest {
+                    bucket: self.config.bucket_name.clone(),
+                    ..Default::default()
+                },
+                json_data,
+                &upload_type,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to write attributes for address {} to GCS",
+                    token_address
+                )
+            })?;
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
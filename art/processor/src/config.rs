@@ -60,5 +60,33 @@ pub struct Config {
     #[serde(default)]
     pub health_server_config: HealthServerConfig,
 
-    pub bevy_width: u32,
+    /// The width of the images we render. Defaults to the width the generator's
+    /// art is published at.
+    #[serde(default)]
+    pub bevy_width: Option<u32>,
+
+    /// A YAML file declaring the overlays to draw over the images. If not given we
+    /// use the ones at `artcore::DEFAULT_OVERLAYS`, relative to the working directory.
+    #[serde(default)]
+    pub overlays: Option<PathBuf>,
+
+    /// Which renderer to use to generate the images.
+    #[serde(default)]
+    pub renderer: Renderer,
+
+    /// Have the Bevy app draw in a window rather than offscreen, e.g. to watch what
+    /// it renders. This needs a display, and the processor built with the `window`
+    /// feature.
+    #[serde(default)]
+    pub bevy_window: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub enum Renderer {
+    /// Render with the Bevy app. This requires a GPU, and a display if
+    /// `bevy_window` is set.
+    #[default]
+    Bevy,
+    /// Render with the software rasterizer, which requires neither.
+    Cpu,
 }
@@ -6,11 +6,13 @@ mod processor;
 mod run;
 mod storage;
 
-use crate::config::{Args, Config};
+use crate::config::{Args, Config, Renderer};
 use anyhow::{Context as AnyhowContext, Result};
-use bevyapp::{run_bevy_app, BevyChannels};
+use artcore::{GeneratorRegistry, Overlays, PaletteSet};
+use bevyapp::{start_render_service, ImageRenderer};
 use clap::Parser;
 use run::run;
+use std::sync::Arc;
 use storage::PostgresStorage;
 use tokio::runtime::Builder;
 use tracing::{error, Level};
@@ -48,16 +50,50 @@ async fn main_inner() -> Result<()> {
         .await
         .context("Failed to build blob store")?;
 
-    // Create channels for communication with the Bevy app.
-    let (img_data_sender, img_data_receiver) = crossbeam_channel::bounded::<Vec<u8>>(1);
-    let (token_address_sender, token_address_receiver) = crossbeam_channel::bounded::<String>(1);
+    let generator_version = config.processor_config.processor_config.generator_version;
 
-    let bevy_channels = BevyChannels {
-        token_address_sender,
-        img_data_receiver,
+    let generator_name = config.processor_config.processor_config.generator.clone();
+    let generator = GeneratorRegistry::default().find(&generator_name)?;
+    let width = config
+        .bevy_width
+        .unwrap_or_else(|| generator.default_width());
+
+    // Load the palette set up front so a bad palette file fails at startup rather
+    // than when we try to render the first token.
+    let palettes_path = config.processor_config.processor_config.palettes.clone();
+    let palettes = PaletteSet::load_or_default(palettes_path.as_deref())
+        .context("Failed to load palette set")?;
+    let palettes = Arc::new(palettes);
+
+    // Same for the overlays, and their images.
+    let overlays =
+        Overlays::load_or_default(config.overlays.as_deref()).context("Failed to load overlays")?;
+
+    let image_renderer = match config.renderer {
+        Renderer::Bevy => ImageRenderer::Bevy(start_render_service(
+            width,
+            generator_name,
+            generator_version,
+            palettes_path,
+            config.overlays,
+            config.bevy_window,
+        )?),
+        Renderer::Cpu => ImageRenderer::Cpu {
+            width,
+            palettes: palettes.clone(),
+            overlays: Arc::new(overlays),
+        },
     };
 
-    let mut tasks = run(config.processor_config, blob_store, storage, bevy_channels).await?;
+    let mut tasks = run(
+        config.processor_config,
+        blob_store,
+        storage,
+        image_renderer,
+        generator,
+        palettes,
+    )
+    .await?;
 
     // Start the health server.
     let health_server = tokio::spawn(async {
@@ -67,9 +103,7 @@ async fn main_inner() -> Result<()> {
 
     tasks.push(health_server);
 
-    run_bevy_app(config.bevy_width, img_data_sender, token_address_receiver);
-
-    // Wait for all the tasks. We don't actually get here, the bevy app blocks.
+    // Wait for all the tasks.
     let result = futures::future::select_all(tasks).await;
 
     Err(anyhow::anyhow!(
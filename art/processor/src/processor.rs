@@ -1,4 +1,4 @@
-use crate::{bevyapp::BevyChannels, blob_store::BlobStoreTrait};
+use crate::{bevyapp::ImageRenderer, blob_store::BlobStoreTrait};
 use anyhow::{Context as AnyhowContext, Result};
 use aptos_processor_sdk::{
     aptos_protos::transaction::v1::{
@@ -7,36 +7,74 @@ use aptos_processor_sdk::{
     },
     processor::{ProcessingResult, ProcessorTrait},
 };
+use artcore::{ArtGenerator, GeneratorVersion, PaletteSet, DEFAULT_GENERATOR};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tracing::info;
 
-const MODULE_NAME: &str = "summits_token";
-
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct SummitsProcessorConfig {
     // TODO: This should be an Address instead
     pub contract_address: String,
+
+    /// The name of the Move module in the contract that mints the tokens.
+    #[serde(default = "default_module_name")]
+    pub module_name: String,
+
+    /// The name of the generator to draw the collection's art with.
+    #[serde(default = "default_generator")]
+    pub generator: String,
+
+    /// The version of the generation algorithm to render the tokens with. Tokens in
+    /// a collection must always be rendered with the same version. Defaults to v1,
+    /// which the 2024 summit was minted with, so configs from before there were
+    /// versions still render the same art.
+    #[serde(default = "default_generator_version")]
+    pub generator_version: GeneratorVersion,
+
+    /// A YAML file with the palette set to pick colors from. If not given we use the
+    /// default palette set built in to the binary. Like the generator version, this
+    /// must not change once tokens have been rendered with it.
+    #[serde(default)]
+    pub palettes: Option<PathBuf>,
+}
+
+fn default_module_name() -> String {
+    "summits_token".to_string()
+}
+
+fn default_generator() -> String {
+    DEFAULT_GENERATOR.to_string()
+}
+
+fn default_generator_version() -> GeneratorVersion {
+    GeneratorVersion::V1
 }
 
 #[derive(Debug)]
 pub struct SummitsProcessor {
     config: SummitsProcessorConfig,
     blob_store: Arc<dyn BlobStoreTrait>,
-    bevy_channels: BevyChannels,
+    image_renderer: ImageRenderer,
+    generator: Arc<dyn ArtGenerator>,
+    palettes: Arc<PaletteSet>,
 }
 
 impl SummitsProcessor {
     pub fn new(
         config: SummitsProcessorConfig,
         blob_store: Arc<dyn BlobStoreTrait>,
-        bevy_channels: BevyChannels,
+        image_renderer: ImageRenderer,
+        generator: Arc<dyn ArtGenerator>,
+        palettes: Arc<PaletteSet>,
     ) -> Result<Self> {
         Ok(Self {
             config,
             blob_store,
-            bevy_channels,
+            image_renderer,
+            generator,
+            palettes,
         })
     }
 }
@@ -72,17 +110,20 @@ impl ProcessorTrait for SummitsProcessor {
         for txn_hash in token_addresses_to_write {
             info!("Writing image for txn {}", txn_hash);
 
-            // Send the token address to the Bevy app.
-            self.bevy_channels
-                .token_address_sender
-                .send(txn_hash.clone())?;
-
-            // Pull the image data the app eventually writes.
-            let image = self.bevy_channels.img_data_receiver.recv().unwrap();
+            let image = self
+                .image_renderer
+                .render(&self.generator, &txn_hash, self.config.generator_version)
+                .await?;
 
             self.blob_store.write_image(image, &txn_hash).await?;
 
-            info!("Wrote image for txn {}", txn_hash);
+            let attributes =
+                self.generator
+                    .attributes(&txn_hash, self.config.generator_version, &self.palettes);
+            let json = serde_json::to_vec(&attributes).context("Failed to serialize attributes")?;
+            self.blob_store.write_attributes(json, &txn_hash).await?;
+
+            info!("Wrote image and attributes for txn {}", txn_hash);
         }
 
         Ok(ProcessingResult {
@@ -104,14 +145,14 @@ impl SummitsProcessor {
             EntryFunctionId {
                 module: Some(MoveModuleId {
                     address: self.config.contract_address.clone(),
-                    name: MODULE_NAME.to_string(),
+                    name: self.config.module_name.clone(),
                 }),
                 name: "mint".to_string(),
             },
             EntryFunctionId {
                 module: Some(MoveModuleId {
                     address: self.config.contract_address.clone(),
-                    name: MODULE_NAME.to_string(),
+                    name: self.config.module_name.clone(),
                 }),
                 name: "mint_to".to_string(),
             },
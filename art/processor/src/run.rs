@@ -4,7 +4,7 @@
 
 use super::storage::{CommonStorageConfig, PostgresStorage};
 use crate::{
-    bevyapp::BevyChannels,
+    bevyapp::ImageRenderer,
     blob_store::BlobStoreTrait,
     processor::{SummitsProcessor, SummitsProcessorConfig},
 };
@@ -15,6 +15,7 @@ use aptos_processor_sdk::{
     progress_storage::ProgressStorageTrait,
     stream_subscriber::{GrpcStreamSubscriber, GrpcStreamSubscriberConfig, StreamSubscriberTrait},
 };
+use artcore::{ArtGenerator, PaletteSet};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::task::JoinHandle;
@@ -35,13 +36,21 @@ pub async fn run(
     config: RunConfig,
     blob_store: Arc<dyn BlobStoreTrait>,
     storage: PostgresStorage,
-    bevy_channels: BevyChannels,
+    image_renderer: ImageRenderer,
+    generator: Arc<dyn ArtGenerator>,
+    palettes: Arc<PaletteSet>,
 ) -> Result<Vec<JoinHandle<()>>> {
     // Build the question processor, which is what processes transactions and updates the
     // question storage and the DB.
     let processor = Arc::new(
-        SummitsProcessor::new(config.processor_config.clone(), blob_store, bevy_channels)
-            .context("Failed to build processor")?,
+        SummitsProcessor::new(
+            config.processor_config.clone(),
+            blob_store,
+            image_renderer,
+            generator,
+            palettes,
+        )
+        .context("Failed to build processor")?,
     );
 
     // From the DB, read the last version we processed.
@@ -19,3 +19,8 @@ rm -rf ../frontend/src/summits
 mv /tmp/summits/small.wasm /tmp/summits/summits_bg.wasm
 cp -R /tmp/summits ../frontend/src/summits
 
+# The web page fetches the overlays from its public assets rather than having them
+# built in to the wasm.
+rm -rf ../frontend/public/overlays
+mkdir ../frontend/public/overlays
+cp assets/overlays.yaml assets/*.png ../frontend/public/overlays
@@ -1,13 +1,64 @@
-use artcore::{AppConfig, WebConfig};
+use artcore::{
+    AppConfig, GeneratorVersion, OverlaySet, OverlaySources, WebConfig, DEFAULT_GENERATOR,
+};
+use js_sys::{Map, Uint8Array};
+use std::{io, path::Path};
 use wasm_bindgen::prelude::*;
 
+/// There is no filesystem in the browser, so the page fetches the overlays from its
+/// public assets. The YAML is given as if it were at this path, for errors and so the
+/// paths of the images are as they are declared.
+const OVERLAYS_PATH: &str = "overlays.yaml";
+
+/// The images the overlays declared in the YAML need, which the page should fetch
+/// and pass to `run`.
+#[wasm_bindgen]
+pub fn overlay_paths(overlays_yaml: &str) -> Result<Vec<String>, JsError> {
+    let set = OverlaySet::from_yaml(overlays_yaml, Path::new(OVERLAYS_PATH))?;
+    Ok(set
+        .overlays
+        .iter()
+        .map(|overlay| overlay.path.display().to_string())
+        .collect())
+}
+
+/// Run the app in the canvas. `overlay_images` maps each of the `overlay_paths` to
+/// the PNG data of the image.
 #[wasm_bindgen]
-pub fn run(width: u32, token_address: String, html_canvas_id: String) {
+pub fn run(
+    width: u32,
+    token_address: String,
+    html_canvas_id: String,
+    overlays_yaml: &str,
+    overlay_images: &Map,
+) -> Result<(), JsError> {
+    let read = |path: &Path| {
+        let data = overlay_images.get(&JsValue::from_str(&path.display().to_string()));
+        if data.is_undefined() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "not fetched by the web page",
+            ));
+        }
+        Ok(Uint8Array::new(&data).to_vec())
+    };
+    let overlays = OverlaySources::from_yaml(overlays_yaml, Path::new(OVERLAYS_PATH), read)?;
+
     let app_config = AppConfig {
-        width: width as f32,
+        width: Some(width as f32),
+        generator: DEFAULT_GENERATOR.to_string(),
         initial_token_address: token_address,
+        generator_version: GeneratorVersion::V1,
+        palettes: None,
+        smooth_paths: None,
+        overlays: None,
         paused: false,
+        offscreen: false,
+    };
+    let web_config = WebConfig {
+        html_canvas_id,
+        overlays,
     };
-    let web_config = WebConfig { html_canvas_id };
-    app_config.build(Some(web_config)).run();
+    app_config.build(Some(web_config))?.run();
+    Ok(())
 }
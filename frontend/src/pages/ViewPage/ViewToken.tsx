@@ -1,18 +1,38 @@
 import { Box, Flex, Link, Text } from "@chakra-ui/react";
 import { useEffect, useState } from "react";
-import init, { run } from "../../summits/summits";
+import init, { overlay_paths, run } from "../../summits/summits";
 
 const CANVAS_ID = "summitcanvas";
 
+// Where the overlays drawn over the art are, in the public assets.
+const OVERLAYS_DIR = "/overlays";
+
+// Fetch the overlays and the images they need, keyed by the path they're declared
+// with, so the wasm can draw them without having them built in.
+async function fetchOverlays(): Promise<[string, Map<string, Uint8Array>]> {
+  const fetchOk = async (path: string) => {
+    const response = await fetch(`${OVERLAYS_DIR}/${path}`);
+    if (!response.ok) {
+      throw new Error(`Failed to fetch overlay ${path}: ${response.status}`);
+    }
+    return response;
+  };
+  const overlaysYaml = await (await fetchOk("overlays.yaml")).text();
+  const images = new Map<string, Uint8Array>();
+  for (const path of overlay_paths(overlaysYaml)) {
+    const data = await (await fetchOk(path)).arrayBuffer();
+    images.set(path, new Uint8Array(data));
+  }
+  return [overlaysYaml, images];
+}
+
 export const ViewToken = ({ tokenAddress }: { tokenAddress: string }) => {
   const [loading, setLoading] = useState(false);
   const [loaded, setLoaded] = useState(false);
 
-  // If this width doesn't match the width used elsewhere, the generation will run
-  // differently due to how the values for the mountains are generated (based on
-  // width rather than just generating heaps of points and then throwing some away
-  // if the actual width is smaller). As such, to scale down the canvas after the
-  // fact we do a css transform.
+  // The art looks the same at any width, this only sets the resolution it is drawn
+  // at. We draw at the same width as the images we publish and then scale the canvas
+  // down to fit the page with a css transform.
   const renderWidth = 2000;
 
   // How much of the page the art should take up.
@@ -47,12 +67,19 @@ export const ViewToken = ({ tokenAddress }: { tokenAddress: string }) => {
     // const wasmArrayBuffer = await response.arrayBuffer();
 
     await init();
+    const [overlaysYaml, overlayImages] = await fetchOverlays();
     setLoading(false);
     setLoaded(true);
 
     // This blocks forever.
     console.log("Generating for token", tokenAddress);
-    run(renderWidth, tokenAddress, `#${CANVAS_ID}`);
+    run(
+      renderWidth,
+      tokenAddress,
+      `#${CANVAS_ID}`,
+      overlaysYaml,
+      overlayImages,
+    );
   }
 
   let button = null;
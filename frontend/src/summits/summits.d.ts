@@ -1,14 +1,23 @@
 /* tslint:disable */
 /* eslint-disable */
+/**
+ * @param {string} overlays_yaml
+ * @returns {(string)[]}
+ */
+export function overlay_paths(overlays_yaml: string): string[];
 /**
  * @param {number} width
  * @param {string} token_address
  * @param {string} html_canvas_id
+ * @param {string} overlays_yaml
+ * @param {Map<any, any>} overlay_images
  */
 export function run(
   width: number,
   token_address: string,
   html_canvas_id: string,
+  overlays_yaml: string,
+  overlay_images: Map<any, any>,
 ): void;
 
 export type InitInput =